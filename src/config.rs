@@ -1,9 +1,13 @@
+use super::filter::Filter;
+use super::input::InputDevice;
 use log::warn;
-use sdl2::controller::Button;
-use sdl2::keyboard::Keycode;
+use sdl2::controller::{Axis, Button};
+use sdl2::joystick::HatState;
+use sdl2::keyboard::{Keycode, Mod, LALTMOD, LCTRLMOD, LSHIFTMOD, RALTMOD, RCTRLMOD, RSHIFTMOD};
 use serde::de::{Deserialize, Deserializer, Error, SeqAccess, Unexpected, Visitor};
 use serde_derive::Deserialize;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::fs;
 use std::marker::PhantomData;
@@ -11,19 +15,337 @@ use std::path::{Path, PathBuf};
 use std::str;
 use toml::{value, Value};
 
-const CONTROLLER_FIELDS: [&str; 8] = ["a", "b", "select", "start", "up", "down", "left", "right"];
+// The turbo fields come after the standard buttons, and subtracting `BUTTON_COUNT` from their index
+// gives the index of the button that they toggle.
+pub const CONTROLLER_FIELDS: [&str; 10] = [
+    "a", "b", "select", "start", "up", "down", "left", "right", "turbo_a", "turbo_b",
+];
+pub const BUTTON_COUNT: usize = 8;
+pub const TURBO_BUTTON_COUNT: usize = 2;
+// Number of frames that turbo buttons stay pressed and then released.
+pub const DEFAULT_TURBO_RATE: u32 = 2;
+const MIN_TURBO_RATE: u32 = 2;
+const MAX_TURBO_RATE: u32 = 8;
+pub const SAVE_STATE_SLOTS: usize = 10;
+// The `select_slot_N` actions come after these in `KeybindingsConfig::action_names`.
+const ACTION_FIELDS: [&str; 21] = [
+    "mute",
+    "pause",
+    "reset",
+    "exit",
+    "save_state",
+    "load_state",
+    "increase_speed",
+    "decrease_speed",
+    "rewind",
+    "fast_forward",
+    "frame_advance",
+    "screenshot",
+    "record_av",
+    "dump_audio",
+    "toggle_fullscreen",
+    "cycle_filter",
+    "volume_up",
+    "volume_down",
+    "remap",
+    "next_slot",
+    "prev_slot",
+];
+// Slower speeds raise the sample rate of `neso` past the rate that it can generate samples at.
+pub const MIN_SPEED: f32 = 1.0 / 16.0;
+pub const MAX_SPEED: f32 = 2.0;
+pub const PORT_COUNT: usize = 2;
 
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub enum Direction {
+    Positive,
+    Negative,
+}
+
+impl Direction {
+    fn sign(self) -> char {
+        match self {
+            Direction::Positive => '+',
+            Direction::Negative => '-',
+        }
+    }
+
+    // Splits the trailing "+" or "-" off of an axis binding.
+    fn split_axis_name(value: &str) -> Option<(&str, Direction)> {
+        if let Some(axis_name) = value.strip_suffix('+') {
+            Some((axis_name, Direction::Positive))
+        } else {
+            value
+                .strip_suffix('-')
+                .map(|axis_name| (axis_name, Direction::Negative))
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub enum HatDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl HatDirection {
+    fn from_string(value: &str) -> Option<HatDirection> {
+        match value {
+            "up" => Some(HatDirection::Up),
+            "down" => Some(HatDirection::Down),
+            "left" => Some(HatDirection::Left),
+            "right" => Some(HatDirection::Right),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            HatDirection::Up => "up",
+            HatDirection::Down => "down",
+            HatDirection::Left => "left",
+            HatDirection::Right => "right",
+        }
+    }
+
+    // Diagonal hat states press both of their directions.
+    pub fn from_hat_state(state: HatState) -> &'static [HatDirection] {
+        match state {
+            HatState::Centered => &[],
+            HatState::Up => &[HatDirection::Up],
+            HatState::Down => &[HatDirection::Down],
+            HatState::Left => &[HatDirection::Left],
+            HatState::Right => &[HatDirection::Right],
+            HatState::LeftUp => &[HatDirection::Left, HatDirection::Up],
+            HatState::LeftDown => &[HatDirection::Left, HatDirection::Down],
+            HatState::RightUp => &[HatDirection::Right, HatDirection::Up],
+            HatState::RightDown => &[HatDirection::Right, HatDirection::Down],
+        }
+    }
+}
+
+// A set of the Ctrl, Shift, and Alt modifiers, which do not distinguish between left and right.
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    const CTRL: Modifiers = Modifiers(0x01);
+    const SHIFT: Modifiers = Modifiers(0x02);
+    const ALT: Modifiers = Modifiers(0x04);
+    const NAMES: [(&'static str, Modifiers); 3] = [
+        ("Ctrl", Modifiers::CTRL),
+        ("Shift", Modifiers::SHIFT),
+        ("Alt", Modifiers::ALT),
+    ];
+    // Every combination of the modifiers that is not empty, ordered from the most modifiers to the
+    // fewest.
+    const COMBINATIONS: [Modifiers; 7] = [
+        Modifiers(0x07),
+        Modifiers(0x03),
+        Modifiers(0x05),
+        Modifiers(0x06),
+        Modifiers(0x01),
+        Modifiers(0x02),
+        Modifiers(0x04),
+    ];
+
+    // The modifier of the pressed key itself is left out, so pressing Left Shift is not Shift+Left
+    // Shift.
+    pub fn from_keymod(keymod: Mod, keycode: Keycode) -> Modifiers {
+        let mut modifiers = Modifiers(0);
+        if keymod.intersects(LCTRLMOD | RCTRLMOD)
+            && keycode != Keycode::LCtrl
+            && keycode != Keycode::RCtrl
+        {
+            modifiers.0 |= Modifiers::CTRL.0;
+        }
+        if keymod.intersects(LSHIFTMOD | RSHIFTMOD)
+            && keycode != Keycode::LShift
+            && keycode != Keycode::RShift
+        {
+            modifiers.0 |= Modifiers::SHIFT.0;
+        }
+        if keymod.intersects(LALTMOD | RALTMOD)
+            && keycode != Keycode::LAlt
+            && keycode != Keycode::RAlt
+        {
+            modifiers.0 |= Modifiers::ALT.0;
+        }
+        modifiers
+    }
+
+    fn from_name(name: &str) -> Option<Modifiers> {
+        Modifiers::NAMES
+            .iter()
+            .find(|(modifier_name, _)| modifier_name.eq_ignore_ascii_case(name))
+            .map(|(_, modifier)| *modifier)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    // Returns every subset of the modifiers that is not empty, ordered from the most modifiers to
+    // the fewest.
+    pub fn subsets(self) -> impl Iterator<Item = Modifiers> {
+        Modifiers::COMBINATIONS
+            .iter()
+            .copied()
+            .filter(move |modifiers| modifiers.0 & !self.0 == 0)
+    }
+
+    fn name(self) -> String {
+        Modifiers::NAMES
+            .iter()
+            .filter(|(_, modifier)| self.0 & modifier.0 != 0)
+            .map(|(modifier_name, _)| format!("{}+", modifier_name))
+            .collect()
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 pub enum KeybindingValue {
+    AxisValue(Axis, Direction),
     ButtonValue(Button),
     KeycodeValue(Keycode),
+    JoystickAxisValue(u8, Direction),
+    JoystickButtonValue(u8),
+    JoystickHatValue(u8, HatDirection),
+    // A key pressed while holding modifiers, such as "Ctrl+S".
+    KeyComboValue(Modifiers, Keycode),
+    // A button pressed while holding another button, such as "back+rightshoulder".
+    ButtonChordValue(Button, Button),
 }
 impl KeybindingValue {
+    // A "key:", "pad:", or "joy:" prefix overrides the type of the section that the value is in.
     pub fn from_string(controller_type: &ControllerType, value: &str) -> Option<KeybindingValue> {
-        if *controller_type == ControllerType::Keyboard {
-            Keycode::from_name(value).map(KeybindingValue::KeycodeValue)
+        if let Some((prefix_type, value)) = ControllerType::split_prefix(value) {
+            return KeybindingValue::from_string(&prefix_type, value);
+        }
+
+        match controller_type {
+            ControllerType::Keyboard => KeybindingValue::from_keyboard_string(value),
+            ControllerType::Controller => KeybindingValue::from_controller_string(value),
+            ControllerType::Joystick => KeybindingValue::from_joystick_string(value),
+            ControllerType::Hybrid => None,
+        }
+    }
+
+    // Returns the string that `from_string` parses back into this value.
+    pub fn name(self) -> String {
+        match self {
+            KeybindingValue::AxisValue(axis, direction) => {
+                format!("{}{}", axis.string(), direction.sign())
+            }
+            KeybindingValue::ButtonValue(button) => button.string(),
+            KeybindingValue::KeycodeValue(keycode) => keycode.name(),
+            KeybindingValue::JoystickAxisValue(axis_index, direction) => {
+                format!("axis{}{}", axis_index, direction.sign())
+            }
+            KeybindingValue::JoystickButtonValue(button_index) => {
+                format!("button{}", button_index)
+            }
+            KeybindingValue::JoystickHatValue(hat_index, hat_direction) => {
+                format!("hat{}{}", hat_index, hat_direction.name())
+            }
+            KeybindingValue::KeyComboValue(modifiers, keycode) => {
+                format!("{}{}", modifiers.name(), keycode.name())
+            }
+            KeybindingValue::ButtonChordValue(held_button, button) => {
+                format!("{}+{}", held_button.string(), button.string())
+            }
+        }
+    }
+
+    // Values of a different type than the section are written with a prefix.
+    fn name_in_section(self, controller_type: ControllerType) -> String {
+        let value_type = self.controller_type();
+        match value_type.prefix() {
+            Some(prefix) if value_type != controller_type => format!("{}{}", prefix, self.name()),
+            _ => self.name(),
+        }
+    }
+
+    pub fn controller_type(self) -> ControllerType {
+        match self {
+            KeybindingValue::AxisValue(..)
+            | KeybindingValue::ButtonValue(_)
+            | KeybindingValue::ButtonChordValue(..) => ControllerType::Controller,
+            KeybindingValue::KeycodeValue(_) | KeybindingValue::KeyComboValue(..) => {
+                ControllerType::Keyboard
+            }
+            KeybindingValue::JoystickAxisValue(..)
+            | KeybindingValue::JoystickButtonValue(_)
+            | KeybindingValue::JoystickHatValue(..) => ControllerType::Joystick,
+        }
+    }
+
+    // Modifiers are split off of the front of the value, so "Ctrl++" is Ctrl and the "+" key.
+    fn from_keyboard_string(value: &str) -> Option<KeybindingValue> {
+        let mut modifiers = Modifiers(0);
+        let mut keycode_name = value;
+        while let Some((modifier_name, rest)) = keycode_name.split_once('+') {
+            match Modifiers::from_name(modifier_name) {
+                Some(modifier) if !rest.is_empty() => {
+                    modifiers.0 |= modifier.0;
+                    keycode_name = rest;
+                }
+                _ => break,
+            }
+        }
+
+        let keycode = Keycode::from_name(keycode_name)?;
+        if modifiers.is_empty() {
+            Some(KeybindingValue::KeycodeValue(keycode))
         } else {
-            Button::from_string(value).map(KeybindingValue::ButtonValue)
+            Some(KeybindingValue::KeyComboValue(modifiers, keycode))
+        }
+    }
+
+    fn from_controller_string(value: &str) -> Option<KeybindingValue> {
+        let chord = value.split_once('+').and_then(|(held_name, button_name)| {
+            Some(KeybindingValue::ButtonChordValue(
+                Button::from_string(held_name)?,
+                Button::from_string(button_name)?,
+            ))
+        });
+        if chord.is_some() {
+            return chord;
+        }
+
+        match Direction::split_axis_name(value) {
+            Some((axis_name, direction)) => {
+                Axis::from_string(axis_name).map(|axis| KeybindingValue::AxisValue(axis, direction))
+            }
+            None => Button::from_string(value).map(KeybindingValue::ButtonValue),
+        }
+    }
+
+    // Parses raw joystick bindings, which look like "button0", "hat0up", or "axis0+".
+    fn from_joystick_string(value: &str) -> Option<KeybindingValue> {
+        if let Some(button_index) = value.strip_prefix("button") {
+            button_index
+                .parse()
+                .ok()
+                .map(KeybindingValue::JoystickButtonValue)
+        } else if let Some(axis) = value.strip_prefix("axis") {
+            let (axis_index, direction) = Direction::split_axis_name(axis)?;
+            axis_index
+                .parse()
+                .ok()
+                .map(|axis_index| KeybindingValue::JoystickAxisValue(axis_index, direction))
+        } else if let Some(hat) = value.strip_prefix("hat") {
+            let (hat_index, hat_direction) = hat.split_at(hat.find(|c: char| !c.is_ascii_digit())?);
+            let hat_direction = HatDirection::from_string(hat_direction)?;
+            hat_index
+                .parse()
+                .ok()
+                .map(|hat_index| KeybindingValue::JoystickHatValue(hat_index, hat_direction))
+        } else {
+            None
         }
     }
 }
@@ -68,24 +390,120 @@ impl<'de> Deserialize<'de> for RawKeybindingValues {
     }
 }
 
-#[derive(Deserialize, PartialEq)]
+#[derive(Clone, Copy, Deserialize, PartialEq)]
 pub enum ControllerType {
     Controller,
+    Joystick,
     Keyboard,
+    // Every value in a hybrid section has a prefix that gives its type.
+    Hybrid,
+}
+
+impl ControllerType {
+    pub fn name(self) -> &'static str {
+        match self {
+            ControllerType::Controller => "Controller",
+            ControllerType::Joystick => "Joystick",
+            ControllerType::Keyboard => "Keyboard",
+            ControllerType::Hybrid => "Hybrid",
+        }
+    }
+
+    fn prefix(self) -> Option<&'static str> {
+        match self {
+            ControllerType::Controller => Some("pad:"),
+            ControllerType::Joystick => Some("joy:"),
+            ControllerType::Keyboard => Some("key:"),
+            ControllerType::Hybrid => None,
+        }
+    }
+
+    fn split_prefix(value: &str) -> Option<(ControllerType, &str)> {
+        [
+            ControllerType::Controller,
+            ControllerType::Joystick,
+            ControllerType::Keyboard,
+        ]
+        .iter()
+        .find_map(|controller_type| {
+            let prefix = controller_type.prefix()?;
+            value
+                .strip_prefix(prefix)
+                .map(|value| (*controller_type, value))
+        })
+    }
+
+    fn expected_value(&self) -> &'static str {
+        match self {
+            ControllerType::Controller => "a string as a button or axis name.",
+            ControllerType::Joystick => "a string as a joystick button, hat, or axis.",
+            ControllerType::Keyboard => "a string as a keycode string.",
+            ControllerType::Hybrid => "a string with a `key:`, `pad:`, or `joy:` prefix.",
+        }
+    }
+}
+
+// A device is selected by its index or by its GUID. Indices are assigned in the order that devices
+// are connected, and a reconnected device gets back the index of a disconnected device with the
+// same GUID.
+#[derive(Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum DeviceSelector {
+    Index(u32),
+    Guid(String),
+}
+
+impl DeviceSelector {
+    fn to_toml(&self) -> Value {
+        match self {
+            DeviceSelector::Index(index) => Value::Integer(i64::from(*index)),
+            DeviceSelector::Guid(guid) => Value::String(guid.clone()),
+        }
+    }
 }
 
 #[derive(Deserialize)]
 struct RawKeybindingConfig {
     #[serde(rename = "type")]
     controller_type: ControllerType,
+    #[serde(default)]
+    device: Option<DeviceSelector>,
+    #[serde(default)]
+    turbo_rate: Option<u32>,
     #[serde(flatten)]
     raw_keybindings: HashMap<String, RawKeybindingValues>,
 }
 
 impl RawKeybindingConfig {
+    fn parse_keybindings<E>(self) -> Result<HashMap<String, Vec<KeybindingValue>>, E>
+    where
+        E: Error,
+    {
+        let controller_type = self.controller_type;
+        let mut keybindings = HashMap::new();
+        for entry in self.raw_keybindings {
+            let mut keybinding_values = Vec::new();
+            for raw_keybinding_str in (entry.1).0 {
+                let keybinding_value =
+                    KeybindingValue::from_string(&controller_type, &raw_keybinding_str)
+                        .ok_or_else(|| {
+                            E::invalid_value(
+                                Unexpected::Str(&raw_keybinding_str),
+                                &controller_type.expected_value(),
+                            )
+                        })?;
+                keybinding_values.push(keybinding_value);
+            }
+            keybindings.insert(entry.0, keybinding_values);
+        }
+        Ok(keybindings)
+    }
+
     fn default_keyboard() -> Self {
         RawKeybindingConfig {
             controller_type: ControllerType::Keyboard,
+            device: None,
+            turbo_rate: None,
             raw_keybindings: vec![
                 ("a".to_string(), RawKeybindingValues(vec!["P".to_owned()])),
                 ("b".to_string(), RawKeybindingValues(vec!["O".to_owned()])),
@@ -119,6 +537,8 @@ impl RawKeybindingConfig {
     fn default_controller() -> Self {
         RawKeybindingConfig {
             controller_type: ControllerType::Controller,
+            device: None,
+            turbo_rate: None,
             raw_keybindings: vec![
                 ("a".to_string(), RawKeybindingValues(vec!["a".to_owned()])),
                 ("b".to_string(), RawKeybindingValues(vec!["b".to_owned()])),
@@ -151,15 +571,178 @@ impl RawKeybindingConfig {
             .collect(),
         }
     }
+
+    fn default_joystick() -> Self {
+        RawKeybindingConfig {
+            controller_type: ControllerType::Joystick,
+            device: None,
+            turbo_rate: None,
+            raw_keybindings: vec![
+                (
+                    "a".to_string(),
+                    RawKeybindingValues(vec!["button0".to_owned()]),
+                ),
+                (
+                    "b".to_string(),
+                    RawKeybindingValues(vec!["button1".to_owned()]),
+                ),
+                (
+                    "select".to_string(),
+                    RawKeybindingValues(vec!["button2".to_owned()]),
+                ),
+                (
+                    "start".to_string(),
+                    RawKeybindingValues(vec!["button3".to_owned()]),
+                ),
+                (
+                    "up".to_string(),
+                    RawKeybindingValues(vec!["hat0up".to_owned(), "axis1-".to_owned()]),
+                ),
+                (
+                    "down".to_string(),
+                    RawKeybindingValues(vec!["hat0down".to_owned(), "axis1+".to_owned()]),
+                ),
+                (
+                    "left".to_string(),
+                    RawKeybindingValues(vec!["hat0left".to_owned(), "axis0-".to_owned()]),
+                ),
+                (
+                    "right".to_string(),
+                    RawKeybindingValues(vec!["hat0right".to_owned(), "axis0+".to_owned()]),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+}
+
+// Values that do not match the type of the section are written with their prefix. A single value
+// is written as a string and anything else as an array.
+fn keybinding_values_to_toml(
+    keybinding_values: &[KeybindingValue],
+    controller_type: ControllerType,
+) -> Value {
+    let mut names = keybinding_values
+        .iter()
+        .map(|keybinding_value| Value::String(keybinding_value.name_in_section(controller_type)))
+        .collect::<Vec<Value>>();
+    match names.pop() {
+        Some(name) if names.is_empty() => name,
+        Some(name) => {
+            names.push(name);
+            Value::Array(names)
+        }
+        None => Value::Array(names),
+    }
+}
+
+fn is_keyboard_value(keybinding_value: &KeybindingValue) -> bool {
+    keybinding_value.controller_type() == ControllerType::Keyboard
+}
+
+// Merges the configured keybindings of a section over its defaults. Default keys are kept for a
+// field that is only bound to controller or joystick inputs so that adding a controller binding
+// does not take away its keyboard hotkey, and default keybindings that are configured for another
+// field are dropped.
+fn merge_keybindings(
+    mut default_keybindings: HashMap<String, Vec<KeybindingValue>>,
+    keybindings: HashMap<String, Vec<KeybindingValue>>,
+) -> HashMap<String, Vec<KeybindingValue>> {
+    let configured_values = keybindings
+        .values()
+        .flatten()
+        .cloned()
+        .collect::<Vec<KeybindingValue>>();
+    for keybinding_values in default_keybindings.values_mut() {
+        keybinding_values.retain(|keybinding_value| !configured_values.contains(keybinding_value));
+    }
+
+    for (field, mut keybinding_values) in keybindings {
+        let default_values = default_keybindings.remove(&field).unwrap_or_default();
+        if !keybinding_values.is_empty() && !keybinding_values.iter().any(is_keyboard_value) {
+            keybinding_values.extend(default_values.into_iter().filter(is_keyboard_value));
+        }
+        default_keybindings.insert(field, keybinding_values);
+    }
+    default_keybindings
 }
 
+#[derive(Clone)]
 pub struct ControllerConfig {
+    pub controller_type: ControllerType,
     pub keybinding_map: HashMap<KeybindingValue, usize>,
+    pub device: Option<DeviceSelector>,
+    pub turbo_rate: u32,
 }
 
 impl ControllerConfig {
     fn new(keybinding_map: HashMap<KeybindingValue, usize>) -> Self {
-        ControllerConfig { keybinding_map }
+        ControllerConfig {
+            controller_type: ControllerType::Keyboard,
+            keybinding_map,
+            device: None,
+            turbo_rate: DEFAULT_TURBO_RATE,
+        }
+    }
+
+    // Replaces the keybindings of the field at `field_index` that have the same type as the new
+    // one. A keybinding can only trigger one field, so it is removed from any other field that it
+    // was bound to.
+    pub fn set_keybinding(&mut self, field_index: usize, keybinding_value: KeybindingValue) {
+        let controller_type = keybinding_value.controller_type();
+        self.keybinding_map.retain(|value, index| {
+            *index != field_index || value.controller_type() != controller_type
+        });
+        self.keybinding_map.insert(keybinding_value, field_index);
+    }
+
+    // Every field is written, including empty ones, so that they do not fall back to their
+    // defaults when the config is parsed again.
+    fn to_toml(&self) -> Value {
+        let mut toml_table = value::Table::new();
+        toml_table.insert(
+            "type".to_string(),
+            Value::String(self.controller_type.name().to_string()),
+        );
+        if let Some(device) = &self.device {
+            toml_table.insert("device".to_string(), device.to_toml());
+        }
+        toml_table.insert(
+            "turbo_rate".to_string(),
+            Value::Integer(i64::from(self.turbo_rate)),
+        );
+        for (field_index, field) in CONTROLLER_FIELDS.iter().enumerate() {
+            let mut keybinding_values = self
+                .keybinding_map
+                .iter()
+                .filter(|(_, index)| **index == field_index)
+                .map(|(keybinding_value, _)| *keybinding_value)
+                .collect::<Vec<KeybindingValue>>();
+            keybinding_values.sort_by_key(|keybinding_value| keybinding_value.name());
+            toml_table.insert(
+                field.to_string(),
+                keybinding_values_to_toml(&keybinding_values, self.controller_type),
+            );
+        }
+        Value::Table(toml_table)
+    }
+
+    pub fn uses_controller(&self) -> bool {
+        self.keybinding_map
+            .keys()
+            .any(|keybinding_value| !is_keyboard_value(keybinding_value))
+    }
+
+    // Keyboard input has no device, so it matches every port.
+    pub fn matches_device(&self, device: Option<&InputDevice>) -> bool {
+        match (&self.device, device) {
+            (Some(DeviceSelector::Index(index)), Some(device)) => *index == device.index,
+            (Some(DeviceSelector::Guid(guid)), Some(device)) => {
+                guid.eq_ignore_ascii_case(&device.guid)
+            }
+            _ => true,
+        }
     }
 }
 
@@ -168,48 +751,42 @@ impl<'de> Deserialize<'de> for ControllerConfig {
     where
         D: Deserializer<'de>,
     {
-        let mut controller_config = ControllerConfig::new(HashMap::new());
-
         let parsed_raw_config = RawKeybindingConfig::deserialize(deserializer)?;
-        let mut raw_config = if parsed_raw_config.controller_type == ControllerType::Keyboard {
-            RawKeybindingConfig::default_keyboard()
-        } else {
-            RawKeybindingConfig::default_controller()
+        let mut controller_config = ControllerConfig::new(HashMap::new());
+        controller_config.controller_type = parsed_raw_config.controller_type;
+        controller_config.device = parsed_raw_config.device.clone();
+        if let Some(turbo_rate) = parsed_raw_config.turbo_rate {
+            if !(MIN_TURBO_RATE..=MAX_TURBO_RATE).contains(&turbo_rate) {
+                return Err(Error::invalid_value(
+                    Unexpected::Unsigned(u64::from(turbo_rate)),
+                    &"an integer between 2 and 8",
+                ));
+            }
+            controller_config.turbo_rate = turbo_rate;
+        }
+        let default_raw_config = match parsed_raw_config.controller_type {
+            ControllerType::Controller => RawKeybindingConfig::default_controller(),
+            ControllerType::Joystick => RawKeybindingConfig::default_joystick(),
+            ControllerType::Keyboard | ControllerType::Hybrid => {
+                RawKeybindingConfig::default_keyboard()
+            }
         };
-        raw_config
-            .raw_keybindings
-            .extend(parsed_raw_config.raw_keybindings.into_iter());
-        let controller_type = raw_config.controller_type;
 
-        for entry in raw_config.raw_keybindings {
-            match CONTROLLER_FIELDS
+        let keybindings = merge_keybindings(
+            default_raw_config.parse_keybindings()?,
+            parsed_raw_config.parse_keybindings()?,
+        );
+        for (field, keybinding_values) in keybindings {
+            let index = CONTROLLER_FIELDS
                 .iter()
-                .position(|field| **field == entry.0)
-            {
-                Some(index) => {
-                    for raw_keybinding_str in (entry.1).0 {
-                        let keybinding =
-                            KeybindingValue::from_string(&controller_type, &raw_keybinding_str)
-                                .ok_or_else(|| {
-                                    let err_msg = if controller_type == ControllerType::Keyboard {
-                                        &"a string as a keycode string."
-                                    } else {
-                                        &"a string as a button name."
-                                    };
-                                    Error::invalid_value(
-                                        Unexpected::Str(&raw_keybinding_str),
-                                        err_msg,
-                                    )
-                                })?;
-                        controller_config.keybinding_map.insert(keybinding, index);
-                    }
-                }
-                None => {
-                    return Err(Error::invalid_value(
-                        Unexpected::Str(&entry.0),
-                        &"a valid controller field",
-                    ));
-                }
+                .position(|controller_field| *controller_field == field)
+                .ok_or_else(|| {
+                    Error::invalid_value(Unexpected::Str(&field), &"a valid controller field")
+                })?;
+            for keybinding_value in keybinding_values {
+                controller_config
+                    .keybinding_map
+                    .insert(keybinding_value, index);
             }
         }
 
@@ -220,6 +797,9 @@ impl<'de> Deserialize<'de> for ControllerConfig {
 impl Default for ControllerConfig {
     fn default() -> Self {
         ControllerConfig {
+            controller_type: ControllerType::Keyboard,
+            device: None,
+            turbo_rate: DEFAULT_TURBO_RATE,
             keybinding_map: vec![
                 (KeybindingValue::KeycodeValue(Keycode::P), 0),
                 (KeybindingValue::KeycodeValue(Keycode::O), 1),
@@ -237,7 +817,9 @@ impl Default for ControllerConfig {
     }
 }
 
+#[derive(Clone)]
 pub struct KeybindingsConfig {
+    pub controller_type: ControllerType,
     pub mute: Vec<KeybindingValue>,
     pub pause: Vec<KeybindingValue>,
     pub reset: Vec<KeybindingValue>,
@@ -246,6 +828,122 @@ pub struct KeybindingsConfig {
     pub load_state: Vec<KeybindingValue>,
     pub increase_speed: Vec<KeybindingValue>,
     pub decrease_speed: Vec<KeybindingValue>,
+    pub rewind: Vec<KeybindingValue>,
+    pub fast_forward: Vec<KeybindingValue>,
+    pub frame_advance: Vec<KeybindingValue>,
+    pub screenshot: Vec<KeybindingValue>,
+    pub record_av: Vec<KeybindingValue>,
+    pub dump_audio: Vec<KeybindingValue>,
+    pub toggle_fullscreen: Vec<KeybindingValue>,
+    pub cycle_filter: Vec<KeybindingValue>,
+    pub volume_up: Vec<KeybindingValue>,
+    pub volume_down: Vec<KeybindingValue>,
+    pub remap: Vec<KeybindingValue>,
+    pub next_slot: Vec<KeybindingValue>,
+    pub prev_slot: Vec<KeybindingValue>,
+    pub select_slot: [Vec<KeybindingValue>; SAVE_STATE_SLOTS],
+}
+
+impl KeybindingsConfig {
+    pub fn action_names() -> Vec<String> {
+        ACTION_FIELDS
+            .iter()
+            .map(|field| field.to_string())
+            .chain((0..SAVE_STATE_SLOTS).map(|slot| format!("select_slot_{}", slot)))
+            .collect()
+    }
+
+    fn parse_slot(name: &str) -> Option<usize> {
+        name.strip_prefix("select_slot_")?
+            .parse::<usize>()
+            .ok()
+            .filter(|slot| *slot < SAVE_STATE_SLOTS)
+    }
+
+    pub fn action(&self, name: &str) -> Option<&Vec<KeybindingValue>> {
+        match name {
+            "mute" => Some(&self.mute),
+            "pause" => Some(&self.pause),
+            "reset" => Some(&self.reset),
+            "exit" => Some(&self.exit),
+            "save_state" => Some(&self.save_state),
+            "load_state" => Some(&self.load_state),
+            "increase_speed" => Some(&self.increase_speed),
+            "decrease_speed" => Some(&self.decrease_speed),
+            "rewind" => Some(&self.rewind),
+            "fast_forward" => Some(&self.fast_forward),
+            "frame_advance" => Some(&self.frame_advance),
+            "screenshot" => Some(&self.screenshot),
+            "record_av" => Some(&self.record_av),
+            "dump_audio" => Some(&self.dump_audio),
+            "toggle_fullscreen" => Some(&self.toggle_fullscreen),
+            "cycle_filter" => Some(&self.cycle_filter),
+            "volume_up" => Some(&self.volume_up),
+            "volume_down" => Some(&self.volume_down),
+            "remap" => Some(&self.remap),
+            "next_slot" => Some(&self.next_slot),
+            "prev_slot" => Some(&self.prev_slot),
+            _ => KeybindingsConfig::parse_slot(name).map(|slot| &self.select_slot[slot]),
+        }
+    }
+
+    pub fn action_mut(&mut self, name: &str) -> Option<&mut Vec<KeybindingValue>> {
+        match name {
+            "mute" => Some(&mut self.mute),
+            "pause" => Some(&mut self.pause),
+            "reset" => Some(&mut self.reset),
+            "exit" => Some(&mut self.exit),
+            "save_state" => Some(&mut self.save_state),
+            "load_state" => Some(&mut self.load_state),
+            "increase_speed" => Some(&mut self.increase_speed),
+            "decrease_speed" => Some(&mut self.decrease_speed),
+            "rewind" => Some(&mut self.rewind),
+            "fast_forward" => Some(&mut self.fast_forward),
+            "frame_advance" => Some(&mut self.frame_advance),
+            "screenshot" => Some(&mut self.screenshot),
+            "record_av" => Some(&mut self.record_av),
+            "dump_audio" => Some(&mut self.dump_audio),
+            "toggle_fullscreen" => Some(&mut self.toggle_fullscreen),
+            "cycle_filter" => Some(&mut self.cycle_filter),
+            "volume_up" => Some(&mut self.volume_up),
+            "volume_down" => Some(&mut self.volume_down),
+            "remap" => Some(&mut self.remap),
+            "next_slot" => Some(&mut self.next_slot),
+            "prev_slot" => Some(&mut self.prev_slot),
+            _ => KeybindingsConfig::parse_slot(name).map(move |slot| &mut self.select_slot[slot]),
+        }
+    }
+
+    // Called on every key press, so the actions are iterated without building their names.
+    pub fn contains(&self, keybinding_value: &KeybindingValue) -> bool {
+        ACTION_FIELDS
+            .iter()
+            .map(|name| self.action(name).expect("Expected action to exist."))
+            .chain(self.select_slot.iter())
+            .any(|keybinding_values| keybinding_values.contains(keybinding_value))
+    }
+
+    // Replaces the keybindings of an action that have the same type as the new one.
+    pub fn set_keybinding(&mut self, name: &str, keybinding_value: KeybindingValue) {
+        let controller_type = keybinding_value.controller_type();
+        let keybinding_values = self.action_mut(name).expect("Expected action to exist.");
+        keybinding_values.retain(|value| value.controller_type() != controller_type);
+        keybinding_values.push(keybinding_value);
+    }
+
+    fn to_toml(&self) -> Value {
+        let mut toml_table = value::Table::new();
+        toml_table.insert(
+            "type".to_string(),
+            Value::String(self.controller_type.name().to_string()),
+        );
+        for name in KeybindingsConfig::action_names() {
+            let keybinding_values = self.action(&name).expect("Expected action to exist.");
+            let toml_value = keybinding_values_to_toml(keybinding_values, self.controller_type);
+            toml_table.insert(name, toml_value);
+        }
+        Value::Table(toml_table)
+    }
 }
 
 impl<'de> Deserialize<'de> for KeybindingsConfig {
@@ -255,39 +953,23 @@ impl<'de> Deserialize<'de> for KeybindingsConfig {
     {
         let mut keybindings_config = KeybindingsConfig::default();
         let raw_config = RawKeybindingConfig::deserialize(deserializer)?;
-        let controller_type = raw_config.controller_type;
+        keybindings_config.controller_type = raw_config.controller_type;
 
-        for entry in raw_config.raw_keybindings {
-            let mut keybindings = Vec::new();
-            for raw_keybinding_str in (entry.1).0.iter() {
-                let keybinding =
-                    KeybindingValue::from_string(&controller_type, &raw_keybinding_str)
-                        .ok_or_else(|| {
-                            let err_msg = if controller_type == ControllerType::Keyboard {
-                                &"a string as a keycode string."
-                            } else {
-                                &"a string as a button name."
-                            };
-                            Error::invalid_value(Unexpected::Str(&raw_keybinding_str), err_msg)
-                        })?;
-                keybindings.push(keybinding);
-            }
-            match entry.0.as_ref() {
-                "mute" => keybindings_config.mute = keybindings,
-                "pause" => keybindings_config.pause = keybindings,
-                "reset" => keybindings_config.reset = keybindings,
-                "exit" => keybindings_config.exit = keybindings,
-                "save_state" => keybindings_config.save_state = keybindings,
-                "load_state" => keybindings_config.load_state = keybindings,
-                "increase_speed" => keybindings_config.increase_speed = keybindings,
-                "decrease_speed" => keybindings_config.decrease_speed = keybindings,
-                _ => {
-                    return Err(Error::invalid_value(
-                        Unexpected::Str(&entry.0),
-                        &"a valid controller field",
-                    ));
-                }
-            }
+        let default_keybindings = KeybindingsConfig::action_names()
+            .into_iter()
+            .map(|name| {
+                let keybinding_values = keybindings_config
+                    .action(&name)
+                    .expect("Expected action to exist.")
+                    .clone();
+                (name, keybinding_values)
+            })
+            .collect();
+        let keybindings = merge_keybindings(default_keybindings, raw_config.parse_keybindings()?);
+        for (name, keybinding_values) in keybindings {
+            *keybindings_config.action_mut(&name).ok_or_else(|| {
+                Error::invalid_value(Unexpected::Str(&name), &"a valid controller field")
+            })? = keybinding_values;
         }
 
         Ok(keybindings_config)
@@ -297,6 +979,7 @@ impl<'de> Deserialize<'de> for KeybindingsConfig {
 impl Default for KeybindingsConfig {
     fn default() -> Self {
         KeybindingsConfig {
+            controller_type: ControllerType::Keyboard,
             mute: vec![KeybindingValue::KeycodeValue(Keycode::M)],
             pause: vec![KeybindingValue::KeycodeValue(Keycode::Space)],
             reset: vec![KeybindingValue::KeycodeValue(Keycode::R)],
@@ -305,6 +988,142 @@ impl Default for KeybindingsConfig {
             load_state: vec![KeybindingValue::KeycodeValue(Keycode::F2)],
             increase_speed: vec![KeybindingValue::KeycodeValue(Keycode::RightBracket)],
             decrease_speed: vec![KeybindingValue::KeycodeValue(Keycode::LeftBracket)],
+            rewind: vec![KeybindingValue::KeycodeValue(Keycode::Backspace)],
+            fast_forward: vec![KeybindingValue::KeycodeValue(Keycode::Tab)],
+            frame_advance: vec![KeybindingValue::KeycodeValue(Keycode::Backslash)],
+            screenshot: vec![KeybindingValue::KeycodeValue(Keycode::F12)],
+            record_av: vec![KeybindingValue::KeycodeValue(Keycode::F11)],
+            dump_audio: vec![KeybindingValue::KeycodeValue(Keycode::F10)],
+            toggle_fullscreen: vec![KeybindingValue::KeycodeValue(Keycode::F)],
+            cycle_filter: vec![KeybindingValue::KeycodeValue(Keycode::F6)],
+            volume_up: vec![KeybindingValue::KeycodeValue(Keycode::Equals)],
+            volume_down: vec![KeybindingValue::KeycodeValue(Keycode::Minus)],
+            remap: vec![KeybindingValue::KeycodeValue(Keycode::F7)],
+            next_slot: vec![KeybindingValue::KeycodeValue(Keycode::F4)],
+            prev_slot: vec![KeybindingValue::KeycodeValue(Keycode::F3)],
+            select_slot: [
+                vec![KeybindingValue::KeycodeValue(Keycode::Num0)],
+                vec![KeybindingValue::KeycodeValue(Keycode::Num1)],
+                vec![KeybindingValue::KeycodeValue(Keycode::Num2)],
+                vec![KeybindingValue::KeycodeValue(Keycode::Num3)],
+                vec![KeybindingValue::KeycodeValue(Keycode::Num4)],
+                vec![KeybindingValue::KeycodeValue(Keycode::Num5)],
+                vec![KeybindingValue::KeycodeValue(Keycode::Num6)],
+                vec![KeybindingValue::KeycodeValue(Keycode::Num7)],
+                vec![KeybindingValue::KeycodeValue(Keycode::Num8)],
+                vec![KeybindingValue::KeycodeValue(Keycode::Num9)],
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AspectRatio {
+    Square,
+    Nes,
+    Television,
+}
+
+impl AspectRatio {
+    fn from_string(value: &str) -> Option<AspectRatio> {
+        match value {
+            "1:1" => Some(AspectRatio::Square),
+            "8:7" => Some(AspectRatio::Nes),
+            "4:3" => Some(AspectRatio::Television),
+            _ => None,
+        }
+    }
+
+    pub fn pixel_aspect_ratio(self) -> f32 {
+        match self {
+            AspectRatio::Square => 1.0,
+            AspectRatio::Nes => 8.0 / 7.0,
+            // Stretch the 256x240 frame to a 4:3 display.
+            AspectRatio::Television => (4.0 / 3.0) / (256.0 / 240.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Overscan {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+impl Overscan {
+    pub fn width(&self) -> u32 {
+        256 - self.left - self.right
+    }
+
+    pub fn height(&self) -> u32 {
+        240 - self.top - self.bottom
+    }
+}
+
+pub struct VideoConfig {
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub fullscreen_desktop: bool,
+    pub integer_scaling: bool,
+    pub aspect_ratio: AspectRatio,
+    pub overscan: Overscan,
+    pub palette: Option<PathBuf>,
+    pub filter: Filter,
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        VideoConfig {
+            scale: 2,
+            fullscreen: false,
+            fullscreen_desktop: false,
+            integer_scaling: false,
+            aspect_ratio: AspectRatio::Square,
+            overscan: Overscan::default(),
+            palette: None,
+            filter: Filter::Nearest,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    Audio,
+    Video,
+    Timer,
+}
+
+impl SyncMode {
+    fn from_string(value: &str) -> Option<SyncMode> {
+        match value {
+            "audio" => Some(SyncMode::Audio),
+            "video" => Some(SyncMode::Video),
+            "timer" => Some(SyncMode::Timer),
+            _ => None,
+        }
+    }
+}
+
+pub struct AudioConfig {
+    pub sync: SyncMode,
+    pub latency: u32,
+    pub sample_rate: u32,
+    pub buffer_size: u16,
+    pub volume: u32,
+    pub device: Option<String>,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            sync: SyncMode::Audio,
+            latency: 64,
+            sample_rate: 44_100,
+            buffer_size: 1024,
+            volume: 100,
+            device: None,
         }
     }
 }
@@ -322,6 +1141,56 @@ fn parse_table(toml_value: Value, details: &str) -> super::Result<value::Table>
     }
 }
 
+fn parse_non_negative_integer(toml_entry: &(String, Value)) -> super::Result<i64> {
+    toml_entry
+        .1
+        .as_integer()
+        .filter(|value| *value >= 0)
+        .ok_or_else(|| {
+            super::Error::from_description(
+                "parsing config",
+                format!("Expected `{}` to be a non-negative integer.", toml_entry.0),
+            )
+        })
+}
+
+fn parse_positive_integer(toml_entry: &(String, Value)) -> super::Result<i64> {
+    toml_entry
+        .1
+        .as_integer()
+        .filter(|value| *value > 0)
+        .ok_or_else(|| {
+            super::Error::from_description(
+                "parsing config",
+                format!("Expected `{}` to be a positive integer.", toml_entry.0),
+            )
+        })
+}
+
+fn parse_bool(toml_entry: &(String, Value)) -> super::Result<bool> {
+    toml_entry.1.as_bool().ok_or_else(|| {
+        super::Error::from_description(
+            "parsing config",
+            format!("Expected `{}` to be a boolean.", toml_entry.0),
+        )
+    })
+}
+
+fn check_speed(speed: f32) -> Option<f32> {
+    Some(speed).filter(|speed| (MIN_SPEED..=MAX_SPEED).contains(speed))
+}
+
+// Parses a speed given as a ratio, such as "1/8", or as a number, such as "0.125".
+pub fn parse_speed(value: &str) -> Option<f32> {
+    let speed = match value.split_once('/') {
+        Some((numerator, denominator)) => {
+            numerator.trim().parse::<f32>().ok()? / denominator.trim().parse::<f32>().ok()?
+        }
+        None => value.trim().parse::<f32>().ok()?,
+    };
+    check_speed(speed)
+}
+
 fn parse_general_config(config: &mut Config, toml_value: Value) -> super::Result<()> {
     let toml_table = parse_table(toml_value, "Expected `general` to be a table.")?;
     for toml_entry in toml_table {
@@ -335,6 +1204,55 @@ fn parse_general_config(config: &mut Config, toml_value: Value) -> super::Result
                 })?)
                 .to_owned();
             }
+            "controller_db" => {
+                config.controller_db = Some(
+                    Path::new(toml_entry.1.as_str().ok_or_else(|| {
+                        super::Error::from_description(
+                            "parsing config",
+                            "Expected `controller_db` to be a string.",
+                        )
+                    })?)
+                    .to_owned(),
+                );
+            }
+            "rewind_interval" => {
+                config.rewind_interval = parse_positive_integer(&toml_entry)? as u32;
+            }
+            "rewind_buffer_size" => {
+                config.rewind_buffer_size =
+                    parse_non_negative_integer(&toml_entry)? as usize * 1024 * 1024;
+            }
+            "axis_deadzone" | "axis_hysteresis" => {
+                let value = parse_non_negative_integer(&toml_entry)?;
+                if value > 100 {
+                    return Err(super::Error::from_description(
+                        "parsing config",
+                        format!("Expected `{}` to be between 0 and 100.", toml_entry.0),
+                    ));
+                }
+                if toml_entry.0 == "axis_deadzone" {
+                    config.axis_deadzone = value as u32;
+                } else {
+                    config.axis_hysteresis = value as u32;
+                }
+            }
+            "speed" => {
+                let speed = match &toml_entry.1 {
+                    Value::String(value) => parse_speed(value),
+                    Value::Float(value) => check_speed(*value as f32),
+                    Value::Integer(value) => check_speed(*value as f32),
+                    _ => None,
+                };
+                config.speed = speed.ok_or_else(|| {
+                    super::Error::from_description(
+                        "parsing config",
+                        "Expected `speed` to be between 1/16 and 2, such as \"1/8\" or 0.5.",
+                    )
+                })?;
+            }
+            "fast_forward_speed" => {
+                config.fast_forward_speed = parse_non_negative_integer(&toml_entry)? as u32;
+            }
             _ => {
                 return Err(super::Error::from_description(
                     "parsing config",
@@ -347,6 +1265,263 @@ fn parse_general_config(config: &mut Config, toml_value: Value) -> super::Result
     Ok(())
 }
 
+fn parse_overscan(toml_value: Value) -> super::Result<Overscan> {
+    let toml_table = parse_table(toml_value, "Expected `overscan` to be a table.")?;
+    let mut overscan = Overscan::default();
+    for toml_entry in toml_table {
+        let (edge, frame_size) = match toml_entry.0.as_ref() {
+            "top" => (&mut overscan.top, 240),
+            "bottom" => (&mut overscan.bottom, 240),
+            "left" => (&mut overscan.left, 256),
+            "right" => (&mut overscan.right, 256),
+            _ => {
+                return Err(super::Error::from_description(
+                    "parsing config",
+                    format!("Unexpected value in `overscan` table: {}.", toml_entry.0),
+                ));
+            }
+        };
+        *edge = u32::try_from(parse_non_negative_integer(&toml_entry)?)
+            .ok()
+            .filter(|value| *value < frame_size)
+            .ok_or_else(|| {
+                super::Error::from_description(
+                    "parsing config",
+                    format!(
+                        "Expected `{}` to be less than {}.",
+                        toml_entry.0, frame_size
+                    ),
+                )
+            })?;
+    }
+
+    if overscan.top + overscan.bottom >= 240 || overscan.left + overscan.right >= 256 {
+        return Err(super::Error::from_description(
+            "parsing config",
+            "Expected `overscan` to leave part of the screen visible.",
+        ));
+    }
+
+    Ok(overscan)
+}
+
+fn parse_video_config(config: &mut Config, toml_value: Value) -> super::Result<()> {
+    let toml_table = parse_table(toml_value, "Expected `video` to be a table.")?;
+    for toml_entry in toml_table {
+        match toml_entry.0.as_ref() {
+            "scale" => {
+                let scale = parse_positive_integer(&toml_entry)?;
+                if scale > 6 {
+                    return Err(super::Error::from_description(
+                        "parsing config",
+                        "Expected `scale` to be between 1 and 6.",
+                    ));
+                }
+                config.video_config.scale = scale as u32;
+            }
+            "fullscreen" => config.video_config.fullscreen = parse_bool(&toml_entry)?,
+            "fullscreen_desktop" => {
+                config.video_config.fullscreen_desktop = parse_bool(&toml_entry)?
+            }
+            "integer_scaling" => config.video_config.integer_scaling = parse_bool(&toml_entry)?,
+            "overscan" => config.video_config.overscan = parse_overscan(toml_entry.1)?,
+            "palette" => {
+                config.video_config.palette = Some(
+                    Path::new(toml_entry.1.as_str().ok_or_else(|| {
+                        super::Error::from_description(
+                            "parsing config",
+                            "Expected `palette` to be a string.",
+                        )
+                    })?)
+                    .to_owned(),
+                );
+            }
+            "aspect_ratio" => {
+                config.video_config.aspect_ratio = toml_entry
+                    .1
+                    .as_str()
+                    .and_then(AspectRatio::from_string)
+                    .ok_or_else(|| {
+                        super::Error::from_description(
+                            "parsing config",
+                            "Expected `aspect_ratio` to be one of \"1:1\", \"8:7\", or \"4:3\".",
+                        )
+                    })?;
+            }
+            "filter" => {
+                config.video_config.filter = toml_entry
+                    .1
+                    .as_str()
+                    .and_then(Filter::from_string)
+                    .ok_or_else(|| {
+                        super::Error::from_description(
+                            "parsing config",
+                            "Expected `filter` to be one of \"nearest\", \"scanlines\", \"ntsc\", \"scale2x\", or \"hq2x\".",
+                        )
+                    })?;
+            }
+            _ => {
+                return Err(super::Error::from_description(
+                    "parsing config",
+                    format!("Unexpected value in `video` table: {}.", toml_entry.0),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_audio_config(config: &mut Config, toml_value: Value) -> super::Result<()> {
+    let toml_table = parse_table(toml_value, "Expected `audio` to be a table.")?;
+    for toml_entry in toml_table {
+        match toml_entry.0.as_ref() {
+            "sync" => {
+                config.audio_config.sync = toml_entry
+                    .1
+                    .as_str()
+                    .and_then(SyncMode::from_string)
+                    .ok_or_else(|| {
+                        super::Error::from_description(
+                            "parsing config",
+                            "Expected `sync` to be one of \"audio\", \"video\", or \"timer\".",
+                        )
+                    })?;
+            }
+            "latency" => config.audio_config.latency = parse_positive_integer(&toml_entry)? as u32,
+            "sample_rate" => {
+                let sample_rate = parse_positive_integer(&toml_entry)?;
+                if !(8000..=192_000).contains(&sample_rate) {
+                    return Err(super::Error::from_description(
+                        "parsing config",
+                        "Expected `sample_rate` to be between 8000 and 192000.",
+                    ));
+                }
+                config.audio_config.sample_rate = sample_rate as u32;
+            }
+            "buffer_size" => {
+                let buffer_size = parse_positive_integer(&toml_entry)?;
+                if buffer_size > i64::from(u16::MAX) || buffer_size.count_ones() != 1 {
+                    return Err(super::Error::from_description(
+                        "parsing config",
+                        "Expected `buffer_size` to be a power of two less than 65536.",
+                    ));
+                }
+                config.audio_config.buffer_size = buffer_size as u16;
+            }
+            "volume" => {
+                let volume = parse_non_negative_integer(&toml_entry)?;
+                if volume > 100 {
+                    return Err(super::Error::from_description(
+                        "parsing config",
+                        "Expected `volume` to be between 0 and 100.",
+                    ));
+                }
+                config.audio_config.volume = volume as u32;
+            }
+            "device" => {
+                config.audio_config.device = Some(
+                    toml_entry
+                        .1
+                        .as_str()
+                        .ok_or_else(|| {
+                            super::Error::from_description(
+                                "parsing config",
+                                "Expected `device` to be a string.",
+                            )
+                        })?
+                        .to_owned(),
+                );
+            }
+            _ => {
+                return Err(super::Error::from_description(
+                    "parsing config",
+                    format!("Unexpected value in `audio` table: {}.", toml_entry.0),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn read_config_table<P>(config_path: P) -> super::Result<value::Table>
+where
+    P: AsRef<Path>,
+{
+    let config_file_buffer =
+        fs::read(&config_path).map_err(|err| super::Error::new("reading config", &err))?;
+    let toml_value = str::from_utf8(&config_file_buffer)
+        .map_err(|err| super::Error::new("reading config", &err))?
+        .parse::<toml::Value>()
+        .map_err(|err| super::Error::new("parsing config", &err))?;
+    parse_table(toml_value, "Expected table at root of config.")
+}
+
+// Returns the name of the table that a line of a TOML file starts, or an empty name if it starts
+// an array of tables.
+fn parse_table_header(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line.starts_with("[[") {
+        return Some("");
+    }
+    let name = line.strip_prefix('[')?.split(']').next()?;
+    Some(name.trim().trim_matches('"'))
+}
+
+// Replaces the table named `name` in the TOML source with `toml_table`, or appends it if there is
+// no such table. Everything outside of the table is kept as it is. Comments and blank lines at the
+// end of the table are also kept since they usually describe the next table.
+fn replace_config_table(source: &str, name: &str, toml_table: &Value) -> super::Result<String> {
+    let table_string = format!(
+        "[{}]\n{}",
+        name,
+        toml::to_string(toml_table).map_err(|err| super::Error::new("serializing config", &err))?
+    );
+    let lines = source.lines().collect::<Vec<&str>>();
+    let start = match lines
+        .iter()
+        .position(|line| parse_table_header(line) == Some(name))
+    {
+        Some(start) => start,
+        None => {
+            let mut config_string = source.to_string();
+            if !config_string.is_empty() {
+                if !config_string.ends_with('\n') {
+                    config_string.push('\n');
+                }
+                config_string.push('\n');
+            }
+            config_string.push_str(&table_string);
+            return Ok(config_string);
+        }
+    };
+
+    let mut end = lines[start + 1..]
+        .iter()
+        .position(|line| parse_table_header(line).is_some())
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    while end > start + 1 {
+        let line = lines[end - 1].trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            break;
+        }
+        end -= 1;
+    }
+
+    let mut config_string = String::new();
+    for line in &lines[..start] {
+        config_string.push_str(line);
+        config_string.push('\n');
+    }
+    config_string.push_str(&table_string);
+    for line in &lines[end..] {
+        config_string.push_str(line);
+        config_string.push('\n');
+    }
+    Ok(config_string)
+}
+
 pub fn get_config_path<P>(config_path_opt: Option<P>) -> PathBuf
 where
     P: AsRef<Path>,
@@ -364,9 +1539,20 @@ where
 }
 
 pub struct Config {
+    pub config_path: PathBuf,
     pub data_path: PathBuf,
+    pub rewind_interval: u32,
+    pub rewind_buffer_size: usize,
+    pub speed: f32,
+    pub fast_forward_speed: u32,
+    pub axis_deadzone: u32,
+    pub axis_hysteresis: u32,
+    // An SDL game controller database with extra mappings to load at startup.
+    pub controller_db: Option<PathBuf>,
     pub keybindings_config: KeybindingsConfig,
-    pub controller_configs: [ControllerConfig; 2],
+    pub video_config: VideoConfig,
+    pub audio_config: AudioConfig,
+    pub controller_configs: [ControllerConfig; PORT_COUNT],
 }
 
 impl Config {
@@ -382,7 +1568,20 @@ impl Config {
         )
     }
 
-    pub fn get_save_state_file<P>(&self, rom_path: P) -> PathBuf
+    pub fn get_save_state_file<P>(&self, rom_path: P, slot: usize) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        let save_state_file_name = rom_path.as_ref().with_extension(format!("state.{}", slot));
+        self.data_path.join(
+            save_state_file_name
+                .file_name()
+                .expect("Expected valid file name."),
+        )
+    }
+
+    // Save states from before slots were added have no slot number.
+    pub fn get_legacy_save_state_file<P>(&self, rom_path: P) -> PathBuf
     where
         P: AsRef<Path>,
     {
@@ -394,13 +1593,77 @@ impl Config {
         )
     }
 
+    pub fn get_save_state_slot_file<P>(&self, rom_path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        let save_state_slot_file_name = rom_path.as_ref().with_extension("slot");
+        self.data_path.join(
+            save_state_slot_file_name
+                .file_name()
+                .expect("Expected valid file name."),
+        )
+    }
+
+    fn get_numbered_file<P>(&self, rom_path: P, extensions: &[&str]) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        let rom_file_stem = rom_path
+            .as_ref()
+            .file_stem()
+            .expect("Expected valid file name.")
+            .to_string_lossy();
+        (0..)
+            .map(|index| self.data_path.join(format!("{}-{}", rom_file_stem, index)))
+            .find(|file_path| {
+                extensions
+                    .iter()
+                    .all(|extension| !file_path.with_extension(extension).exists())
+            })
+            .expect("Expected unused file name.")
+    }
+
+    pub fn get_screenshot_file<P>(&self, rom_path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        self.get_numbered_file(rom_path, &["png"])
+            .with_extension("png")
+    }
+
+    pub fn get_audio_dump_file<P>(&self, rom_path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        self.get_numbered_file(rom_path, &["wav"])
+            .with_extension("wav")
+    }
+
+    pub fn get_recording_file<P>(&self, rom_path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        self.get_numbered_file(rom_path, &["y4m", "wav"])
+    }
+
     pub fn parse_config<P>(config_path: P) -> super::Result<Config>
     where
         P: AsRef<Path>,
     {
         let mut config = Config {
+            config_path: config_path.as_ref().to_owned(),
             data_path: get_default_data_path(),
+            rewind_interval: 2,
+            rewind_buffer_size: 64 * 1024 * 1024,
+            speed: 1.0,
+            fast_forward_speed: 0,
+            axis_deadzone: 50,
+            axis_hysteresis: 10,
+            controller_db: None,
             keybindings_config: KeybindingsConfig::default(),
+            video_config: VideoConfig::default(),
+            audio_config: AudioConfig::default(),
             controller_configs: [ControllerConfig::default(), ControllerConfig::default()],
         };
 
@@ -408,37 +1671,200 @@ impl Config {
             return Ok(config);
         }
 
-        let config_file_buffer =
-            fs::read(&config_path).map_err(|err| super::Error::new("reading config", &err))?;
-        let toml_value = str::from_utf8(&config_file_buffer)
-            .map_err(|err| super::Error::new("reading config", &err))?
-            .parse::<toml::Value>()
-            .map_err(|err| super::Error::new("parsing config", &err))?;
-        let toml_table = parse_table(toml_value, "Expected table at root of config.")?;
-
+        let toml_table = read_config_table(&config_path)?;
         for toml_entry in toml_table {
             let (toml_key, toml_value) = toml_entry;
             match toml_key.as_ref() {
                 "general" => parse_general_config(&mut config, toml_value)?,
+                "video" => parse_video_config(&mut config, toml_value)?,
+                "audio" => parse_audio_config(&mut config, toml_value)?,
                 "keybindings" => {
                     config.keybindings_config = toml_value
                         .try_into::<KeybindingsConfig>()
                         .map_err(|err| super::Error::new("parsing keybindings config", &err))?;
                 }
-                "port-1" => {
-                    config.controller_configs[0] = toml_value
-                        .try_into::<ControllerConfig>()
-                        .map_err(|err| super::Error::new("parsing port-1 config", &err))?
+                "port-1" | "port-2" => {
+                    let port = toml_key["port-".len()..]
+                        .parse::<usize>()
+                        .expect("Expected valid port number.");
+                    config.controller_configs[port - 1] =
+                        toml_value.try_into::<ControllerConfig>().map_err(|err| {
+                            super::Error::new(format!("parsing {} config", toml_key), &err)
+                        })?;
                 }
-                "port-2" => {
-                    config.controller_configs[0] = toml_value
-                        .try_into::<ControllerConfig>()
-                        .map_err(|err| super::Error::new("parsing port-2 config", &err))?
+                "port-3" | "port-4" => {
+                    return Err(super::Error::from_description(
+                        "parsing config",
+                        "Expected only `port-1` and `port-2` to be configured since `neso` does \
+                         not emulate the Four Score.",
+                    ));
                 }
                 _ => warn!("Unexpected value in root of config: {}.", toml_key),
             }
         }
 
+        if config.axis_hysteresis > config.axis_deadzone {
+            return Err(super::Error::from_description(
+                "parsing config",
+                "Expected `axis_hysteresis` to be at most `axis_deadzone`.",
+            ));
+        }
+
         Ok(config)
     }
+
+    // Writes the keybindings and port sections back to the config file. Only those tables are
+    // rewritten, so the other sections and their comments are kept. Comments inside the rewritten
+    // tables are lost, so the previous config file is first copied to `<config>.bak`.
+    pub fn write_config(&self) -> super::Result<()> {
+        let mut config_string = if self.config_path.exists() {
+            let config_string = fs::read_to_string(&self.config_path)
+                .map_err(|err| super::Error::new("reading config", &err))?;
+            let mut backup_path = self.config_path.clone().into_os_string();
+            backup_path.push(".bak");
+            fs::write(backup_path, &config_string)
+                .map_err(|err| super::Error::new("backing up config", &err))?;
+            config_string
+        } else {
+            String::new()
+        };
+
+        config_string = replace_config_table(
+            &config_string,
+            "keybindings",
+            &self.keybindings_config.to_toml(),
+        )?;
+        for (port, controller_config) in self.controller_configs.iter().enumerate() {
+            config_string = replace_config_table(
+                &config_string,
+                &format!("port-{}", port + 1),
+                &controller_config.to_toml(),
+            )?;
+        }
+
+        // A table that is also defined in another way, such as with dotted keys, would now be
+        // defined twice.
+        config_string
+            .parse::<Value>()
+            .map_err(|err| super::Error::new("serializing config", &err))?;
+        if let Some(config_dir) = self.config_path.parent() {
+            fs::create_dir_all(config_dir)
+                .map_err(|err| super::Error::new("creating config directory", &err))?;
+        }
+        fs::write(&self.config_path, config_string)
+            .map_err(|err| super::Error::new("writing config", &err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    const CONFIG: &str = r#"# Comments outside of the rewritten tables are kept.
+[general]
+rewind_interval = 4
+
+[port-1]
+type = "Keyboard"
+a = "P"
+select = ["Left Shift", "Right Shift"]
+
+[port-2]
+type = "Controller"
+device = 1
+b = "x"
+
+[keybindings]
+type = "Controller"
+save_state = "back"
+"#;
+
+    fn write_temp_config(name: &str, contents: &str) -> PathBuf {
+        let config_path = env::temp_dir().join(format!("neso-gui-{}-{}.toml", name, process::id()));
+        fs::write(&config_path, contents).expect("Expected config to be written.");
+        config_path
+    }
+
+    fn remove_temp_config(config_path: PathBuf) {
+        let mut backup_path = config_path.clone().into_os_string();
+        backup_path.push(".bak");
+        fs::remove_file(backup_path).ok();
+        fs::remove_file(config_path).expect("Expected config to be removed.");
+    }
+
+    fn assert_same_keybindings(config: &Config, parsed_config: &Config) {
+        for (controller_config, parsed_controller_config) in config
+            .controller_configs
+            .iter()
+            .zip(parsed_config.controller_configs.iter())
+        {
+            assert!(controller_config.controller_type == parsed_controller_config.controller_type);
+            assert!(controller_config.device == parsed_controller_config.device);
+            assert_eq!(
+                controller_config.turbo_rate,
+                parsed_controller_config.turbo_rate
+            );
+            assert!(controller_config.keybinding_map == parsed_controller_config.keybinding_map);
+        }
+        for name in KeybindingsConfig::action_names() {
+            assert!(
+                config.keybindings_config.action(&name)
+                    == parsed_config.keybindings_config.action(&name),
+                "Expected `{}` to be written.",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn write_config_round_trip() {
+        let config_path = write_temp_config("round-trip", CONFIG);
+        let mut config = Config::parse_config(&config_path).expect("Expected config to parse.");
+        config.controller_configs[0].set_keybinding(0, KeybindingValue::KeycodeValue(Keycode::J));
+        config.controller_configs[1].set_keybinding(1, KeybindingValue::ButtonValue(Button::Y));
+        config.keybindings_config.set_keybinding(
+            "load_state",
+            KeybindingValue::ButtonValue(Button::LeftShoulder),
+        );
+        config
+            .write_config()
+            .expect("Expected config to be written.");
+
+        let parsed_config =
+            Config::parse_config(&config_path).expect("Expected written config to parse.");
+        assert_same_keybindings(&config, &parsed_config);
+        assert_eq!(parsed_config.rewind_interval, 4);
+        let config_string = fs::read_to_string(&config_path).expect("Expected config to be read.");
+        assert!(config_string.starts_with("# Comments outside of the rewritten tables are kept.\n"));
+        let mut backup_path = config_path.clone().into_os_string();
+        backup_path.push(".bak");
+        assert_eq!(
+            fs::read_to_string(backup_path).expect("Expected backup to be read."),
+            CONFIG
+        );
+        remove_temp_config(config_path);
+    }
+
+    #[test]
+    fn write_config_keeps_keybindings_of_another_type() {
+        let config_path = write_temp_config("another-type", CONFIG);
+        let mut config = Config::parse_config(&config_path).expect("Expected config to parse.");
+        config.controller_configs[0].set_keybinding(1, KeybindingValue::ButtonValue(Button::A));
+        config
+            .keybindings_config
+            .set_keybinding("pause", KeybindingValue::ButtonValue(Button::Start));
+        config
+            .write_config()
+            .expect("Expected config to be written.");
+
+        let parsed_config =
+            Config::parse_config(&config_path).expect("Expected written config to parse.");
+        assert_same_keybindings(&config, &parsed_config);
+        assert!(parsed_config.controller_configs[0]
+            .keybinding_map
+            .contains_key(&KeybindingValue::ButtonValue(Button::A)));
+        remove_temp_config(config_path);
+    }
 }
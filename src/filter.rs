@@ -0,0 +1,260 @@
+use std::f32::consts::PI;
+
+const FILTERS: [Filter; 5] = [
+    Filter::Nearest,
+    Filter::Scanlines,
+    Filter::Ntsc,
+    Filter::Scale2x,
+    Filter::Hq2x,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Scanlines,
+    Ntsc,
+    Scale2x,
+    Hq2x,
+}
+
+impl Filter {
+    pub fn from_string(value: &str) -> Option<Filter> {
+        FILTERS
+            .iter()
+            .find(|filter| filter.name() == value)
+            .cloned()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::Nearest => "nearest",
+            Filter::Scanlines => "scanlines",
+            Filter::Ntsc => "ntsc",
+            Filter::Scale2x => "scale2x",
+            Filter::Hq2x => "hq2x",
+        }
+    }
+
+    pub fn next(self) -> Filter {
+        let index = FILTERS
+            .iter()
+            .position(|filter| *filter == self)
+            .expect("Expected filter to exist.");
+        FILTERS[(index + 1) % FILTERS.len()]
+    }
+
+    // Returns the horizontal and vertical scale of the filter output.
+    pub fn scale(self) -> (u32, u32) {
+        match self {
+            Filter::Nearest => (1, 1),
+            Filter::Scanlines => (1, 2),
+            Filter::Ntsc => (2, 1),
+            Filter::Scale2x | Filter::Hq2x => (2, 2),
+        }
+    }
+
+    pub fn apply(self, input: &[u8], width: usize, height: usize, output: &mut [u8], frame: u64) {
+        let image = Image {
+            pixels: input,
+            width,
+            height,
+        };
+        match self {
+            Filter::Nearest => output[..input.len()].copy_from_slice(input),
+            Filter::Scanlines => apply_scanlines(&image, output),
+            Filter::Ntsc => apply_ntsc(&image, output, frame),
+            Filter::Scale2x => apply_scale2x(&image, output),
+            Filter::Hq2x => apply_hq2x(&image, output),
+        }
+    }
+}
+
+type Pixel = [u8; 4];
+
+struct Image<'a> {
+    pixels: &'a [u8],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Image<'a> {
+    // Returns the pixel at the given coordinates, clamped to the edges of the image.
+    fn pixel(&self, x: isize, y: isize) -> Pixel {
+        let x = x.max(0).min(self.width as isize - 1) as usize;
+        let y = y.max(0).min(self.height as isize - 1) as usize;
+        let index = (y * self.width + x) * 4;
+        [
+            self.pixels[index],
+            self.pixels[index + 1],
+            self.pixels[index + 2],
+            self.pixels[index + 3],
+        ]
+    }
+}
+
+fn write_pixel(output: &mut [u8], width: usize, x: usize, y: usize, pixel: Pixel) {
+    let index = (y * width + x) * 4;
+    output[index..index + 4].copy_from_slice(&pixel);
+}
+
+fn apply_scanlines(image: &Image<'_>, output: &mut [u8]) {
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let pixel = image.pixel(x as isize, y as isize);
+            let dark_pixel = [
+                (u16::from(pixel[0]) * 5 / 8) as u8,
+                (u16::from(pixel[1]) * 5 / 8) as u8,
+                (u16::from(pixel[2]) * 5 / 8) as u8,
+                pixel[3],
+            ];
+            write_pixel(output, image.width, x, y * 2, pixel);
+            write_pixel(output, image.width, x, y * 2 + 1, dark_pixel);
+        }
+    }
+}
+
+fn to_yiq(pixel: Pixel) -> (f32, f32, f32) {
+    let r = f32::from(pixel[0]);
+    let g = f32::from(pixel[1]);
+    let b = f32::from(pixel[2]);
+    (
+        0.299 * r + 0.587 * g + 0.114 * b,
+        0.596 * r - 0.274 * g - 0.322 * b,
+        0.211 * r - 0.523 * g + 0.312 * b,
+    )
+}
+
+fn from_yiq(y: f32, i: f32, q: f32) -> Pixel {
+    let clamp = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+    [
+        clamp(y + 0.956 * i + 0.621 * q),
+        clamp(y - 0.272 * i - 0.647 * q),
+        clamp(y - 1.106 * i + 1.703 * q),
+        0xFF,
+    ]
+}
+
+// A simplified composite video filter. Chroma is low-pass filtered so that colors bleed into
+// their neighbors, and the chroma subcarrier leaks into luma and vice versa, which produces the
+// fringing and dot crawl artifacts of a composite signal.
+fn apply_ntsc(image: &Image<'_>, output: &mut [u8], frame: u64) {
+    const LUMA_KERNEL: [f32; 3] = [0.25, 0.5, 0.25];
+    const CHROMA_KERNEL: [f32; 5] = [1.0 / 9.0, 2.0 / 9.0, 3.0 / 9.0, 2.0 / 9.0, 1.0 / 9.0];
+    const CROSSTALK: f32 = 0.15;
+
+    let output_width = image.width * 2;
+    let mut samples = vec![(0.0, 0.0, 0.0); output_width];
+    for y in 0..image.height {
+        for (x, sample) in samples.iter_mut().enumerate() {
+            *sample = to_yiq(image.pixel(x as isize / 2, y as isize));
+        }
+
+        for x in 0..output_width {
+            let sample_at = |offset: isize| {
+                let index = (x as isize + offset).max(0).min(output_width as isize - 1);
+                samples[index as usize]
+            };
+            let luma = LUMA_KERNEL
+                .iter()
+                .enumerate()
+                .map(|(index, weight)| sample_at(index as isize - 1).0 * weight)
+                .sum::<f32>();
+            let (i, q) =
+                CHROMA_KERNEL
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(i, q), (index, weight)| {
+                        let (_, sample_i, sample_q) = sample_at(2 * (index as isize - 2));
+                        (i + sample_i * weight, q + sample_q * weight)
+                    });
+
+            let phase =
+                x as f32 * PI / 2.0 + (y % 3) as f32 * 2.0 * PI / 3.0 + (frame % 2) as f32 * PI;
+            let luma_edge = sample_at(1).0 - sample_at(-1).0;
+            let luma = luma + (i * phase.cos() + q * phase.sin()) * CROSSTALK;
+            let i = i + luma_edge * phase.cos() * CROSSTALK;
+            let q = q + luma_edge * phase.sin() * CROSSTALK;
+            write_pixel(output, output_width, x, y, from_yiq(luma, i, q));
+        }
+    }
+}
+
+// Returns the pixels surrounding the given coordinates in the order: up, left, right, down.
+fn get_neighbors(image: &Image<'_>, x: isize, y: isize) -> (Pixel, Pixel, Pixel, Pixel) {
+    (
+        image.pixel(x, y - 1),
+        image.pixel(x - 1, y),
+        image.pixel(x + 1, y),
+        image.pixel(x, y + 1),
+    )
+}
+
+fn apply_scale2x(image: &Image<'_>, output: &mut [u8]) {
+    let output_width = image.width * 2;
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let e = image.pixel(x as isize, y as isize);
+            let (b, d, f, h) = get_neighbors(image, x as isize, y as isize);
+            let (e0, e1, e2, e3) = if b != h && d != f {
+                (
+                    if d == b { d } else { e },
+                    if b == f { f } else { e },
+                    if d == h { d } else { e },
+                    if h == f { f } else { e },
+                )
+            } else {
+                (e, e, e, e)
+            };
+            write_pixel(output, output_width, x * 2, y * 2, e0);
+            write_pixel(output, output_width, x * 2 + 1, y * 2, e1);
+            write_pixel(output, output_width, x * 2, y * 2 + 1, e2);
+            write_pixel(output, output_width, x * 2 + 1, y * 2 + 1, e3);
+        }
+    }
+}
+
+// Uses the same thresholds as hq2x to decide if two colors are close enough to be treated as the
+// same color.
+fn is_similar(a: Pixel, b: Pixel) -> bool {
+    let (a_y, a_i, a_q) = to_yiq(a);
+    let (b_y, b_i, b_q) = to_yiq(b);
+    (a_y - b_y).abs() <= 48.0 && (a_i - b_i).abs() <= 7.0 && (a_q - b_q).abs() <= 6.0
+}
+
+fn blend(e: Pixel, a: Pixel, b: Pixel) -> Pixel {
+    let mut pixel = [0; 4];
+    for (index, channel) in pixel.iter_mut().enumerate() {
+        *channel =
+            ((u16::from(e[index]) * 2 + u16::from(a[index]) + u16::from(b[index])) / 4) as u8;
+    }
+    pixel
+}
+
+// A simplified hq2x. Edges are detected like scale2x, but with fuzzy color comparisons, and the
+// corners along an edge are interpolated instead of copied.
+fn apply_hq2x(image: &Image<'_>, output: &mut [u8]) {
+    let output_width = image.width * 2;
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let e = image.pixel(x as isize, y as isize);
+            let (b, d, f, h) = get_neighbors(image, x as isize, y as isize);
+            let corner = |a: Pixel, c: Pixel, opposite_a: Pixel, opposite_c: Pixel| {
+                if is_similar(a, c) && !is_similar(a, opposite_a) && !is_similar(c, opposite_c) {
+                    blend(e, a, c)
+                } else {
+                    e
+                }
+            };
+            write_pixel(output, output_width, x * 2, y * 2, corner(b, d, h, f));
+            write_pixel(output, output_width, x * 2 + 1, y * 2, corner(b, f, h, d));
+            write_pixel(output, output_width, x * 2, y * 2 + 1, corner(h, d, b, f));
+            write_pixel(
+                output,
+                output_width,
+                x * 2 + 1,
+                y * 2 + 1,
+                corner(h, f, b, d),
+            );
+        }
+    }
+}
@@ -1,14 +1,18 @@
 use super::{Error, Result};
 use neso::Nes;
-use sdl2::pixels::PixelFormatEnum;
-use sdl2::render::{Texture, TextureCreator};
-use sdl2::video::WindowContext;
+use sdl2::render::Texture;
 use std::slice;
 
 const CHR_BANK_SIZE: usize = 0x400;
 const NAMETABLE_BANK_SIZE: usize = 0x800;
 const PATTERN_TABLE_SIZE: usize = 0x1000;
 
+pub const COLORS_TEXTURE_SIZE: (u32, u32) = (16, 4);
+pub const PALETTES_TEXTURE_SIZE: (u32, u32) = (16, 2);
+pub const PATTERN_TABLE_TEXTURE_SIZE: (u32, u32) = (16 * 8, 16 * 8);
+pub const NAMETABLE_TEXTURE_SIZE: (u32, u32) = (32 * 8, 30 * 8);
+pub const OAM_TEXTURE_SIZE: (u32, u32) = (32 * 8, 4 * 8);
+
 pub struct DebugData<'a> {
     pub colors: &'a [u32],
     pub palettes: &'a [u8],
@@ -47,15 +51,9 @@ impl<'a> DebugData<'a> {
     }
 }
 
-pub fn get_colors_texture<'a>(
-    texture_creator: &'a TextureCreator<WindowContext>,
-    d: &DebugData<'_>,
-) -> Result<Texture<'a>> {
+pub fn update_colors_texture(texture: &mut Texture<'_>, d: &DebugData<'_>) -> Result<()> {
     let cols = 16;
     let rows = 4;
-    let mut texture = texture_creator
-        .create_texture_streaming(PixelFormatEnum::RGB24, cols as u32, rows as u32)
-        .map_err(|err| Error::new("creating colors texture", &err))?;
     texture
         .with_lock(None, |buffer: &mut [u8], _pitch: usize| {
             for i in 0..rows * cols {
@@ -65,18 +63,12 @@ pub fn get_colors_texture<'a>(
             }
         })
         .map_err(|err| Error::from_description("locking colors texture", err))?;
-    Ok(texture)
+    Ok(())
 }
 
-pub fn get_palettes_texture<'a>(
-    texture_creator: &'a TextureCreator<WindowContext>,
-    d: &DebugData<'_>,
-) -> Result<Texture<'a>> {
+pub fn update_palettes_texture(texture: &mut Texture<'_>, d: &DebugData<'_>) -> Result<()> {
     let cols = 16;
     let rows = 2;
-    let mut texture = texture_creator
-        .create_texture_streaming(PixelFormatEnum::RGB24, cols as u32, rows as u32)
-        .map_err(|err| Error::new("creating palettes texture", &err))?;
     texture
         .with_lock(None, |buffer: &mut [u8], _pitch: usize| {
             for i in 0..rows * cols {
@@ -88,20 +80,17 @@ pub fn get_palettes_texture<'a>(
             }
         })
         .map_err(|err| Error::from_description("locking palettes texture", err))?;
-    Ok(texture)
+    Ok(())
 }
 
-pub fn get_pattern_table_texture<'a>(
-    texture_creator: &'a TextureCreator<WindowContext>,
+pub fn update_pattern_table_texture(
+    texture: &mut Texture<'_>,
     d: &DebugData<'_>,
     table_index: usize,
-) -> Result<Texture<'a>> {
+) -> Result<()> {
     let cols = 16;
     let rows = 16;
     let offset = table_index * PATTERN_TABLE_SIZE;
-    let mut texture = texture_creator
-        .create_texture_streaming(PixelFormatEnum::RGB24, cols as u32 * 8, rows as u32 * 8)
-        .map_err(|err| Error::new("creating pattern table texture", &err))?;
     texture
         .with_lock(None, |buffer: &mut [u8], _pitch: usize| {
             for row in 0..rows {
@@ -125,20 +114,17 @@ pub fn get_pattern_table_texture<'a>(
             }
         })
         .map_err(|err| Error::from_description("locking pattern table texture", err))?;
-    Ok(texture)
+    Ok(())
 }
 
-pub fn get_nametable_texture<'a>(
-    texture_creator: &'a TextureCreator<WindowContext>,
+pub fn update_nametable_texture(
+    texture: &mut Texture<'_>,
     d: &DebugData<'_>,
     bank_index: usize,
-) -> Result<Texture<'a>> {
+) -> Result<()> {
     let cols = 32;
     let rows = 30;
     let (nametable, attribute_table) = d.nametable_banks[bank_index].split_at(cols * rows);
-    let mut texture = texture_creator
-        .create_texture_streaming(PixelFormatEnum::RGB24, cols as u32 * 8, rows as u32 * 8)
-        .map_err(|err| Error::new("creating nametable texture", &err))?;
     texture
         .with_lock(None, |buffer: &mut [u8], _pitch: usize| {
             for row in 0..rows {
@@ -171,18 +157,12 @@ pub fn get_nametable_texture<'a>(
             }
         })
         .map_err(|err| Error::from_description("locking nametable texture", err))?;
-    Ok(texture)
+    Ok(())
 }
 
-pub fn get_oam_texture<'a>(
-    texture_creator: &'a TextureCreator<WindowContext>,
-    d: &DebugData<'_>,
-) -> Result<Texture<'a>> {
+pub fn update_oam_texture(texture: &mut Texture<'_>, d: &DebugData<'_>) -> Result<()> {
     let cols = 32;
     let rows = 4;
-    let mut texture = texture_creator
-        .create_texture_streaming(PixelFormatEnum::RGB24, cols as u32 * 8, rows as u32 * 8)
-        .map_err(|err| Error::new("creating oam texture", &err))?;
     texture
         .with_lock(None, |buffer: &mut [u8], _pitch: usize| {
             for s in 0..cols * rows / 2 {
@@ -228,5 +208,5 @@ pub fn get_oam_texture<'a>(
             }
         })
         .map_err(|err| Error::from_description("locking oam texture", err))?;
-    Ok(texture)
+    Ok(())
 }
@@ -0,0 +1,223 @@
+use super::config::{Direction, KeybindingValue};
+use super::{Error, Result};
+use log::{error, info};
+use sdl2::controller::{Axis, GameController};
+use sdl2::joystick::Joystick;
+use sdl2::{GameControllerSubsystem, JoystickSubsystem};
+use std::collections::HashMap;
+
+// Number of disconnected devices whose index is kept for when they are reconnected.
+const MAX_DISCONNECTED_DEVICES: usize = 8;
+
+// Identifies a physical device by its index and GUID. The index is the order in which the device
+// was connected, and reconnecting a device with the same GUID gives it back its old index.
+#[derive(Clone)]
+pub struct InputDevice {
+    pub index: u32,
+    pub guid: String,
+}
+
+// An analog axis of either a game controller or a raw joystick.
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub enum AnalogAxis {
+    Controller(Axis),
+    Joystick(u8),
+}
+
+impl AnalogAxis {
+    pub fn keybinding_value(self, direction: Direction) -> KeybindingValue {
+        match self {
+            AnalogAxis::Controller(axis) => KeybindingValue::AxisValue(axis, direction),
+            AnalogAxis::Joystick(axis_index) => {
+                KeybindingValue::JoystickAxisValue(axis_index, direction)
+            }
+        }
+    }
+}
+
+// The handles are kept so that SDL keeps sending events for the device.
+enum DeviceHandle {
+    Controller(GameController),
+    Joystick(Joystick),
+}
+
+impl DeviceHandle {
+    fn description(&self) -> String {
+        match self {
+            DeviceHandle::Controller(controller) => format!("controller ({})", controller.name()),
+            DeviceHandle::Joystick(joystick) => format!("joystick ({})", joystick.name()),
+        }
+    }
+}
+
+struct OpenDevice {
+    handle: DeviceHandle,
+    device: InputDevice,
+}
+
+// The open game controllers and raw joysticks keyed by their SDL instance ids, which are used in
+// controller and joystick events. Joysticks that have a game controller mapping are only opened
+// as game controllers.
+pub struct Controllers {
+    devices: HashMap<i32, OpenDevice>,
+    // Ordered from the least to the most recently disconnected, and the least recent device is
+    // forgotten once there are more than `MAX_DISCONNECTED_DEVICES`.
+    disconnected_devices: Vec<InputDevice>,
+}
+
+impl Controllers {
+    pub fn open_all(
+        game_controller_subsystem: &GameControllerSubsystem,
+        joystick_subsystem: &JoystickSubsystem,
+    ) -> Result<Self> {
+        let mut controllers = Controllers {
+            devices: HashMap::new(),
+            disconnected_devices: Vec::new(),
+        };
+        let available = game_controller_subsystem
+            .num_joysticks()
+            .map_err(|err| Error::from_description("enumerating joysticks", err))?;
+        for index in 0..available {
+            if game_controller_subsystem.is_game_controller(index) {
+                controllers.open(game_controller_subsystem, joystick_subsystem, index);
+            } else {
+                controllers.open_joystick(game_controller_subsystem, joystick_subsystem, index);
+            }
+        }
+        Ok(controllers)
+    }
+
+    // Returns the index of a disconnected device with the same GUID if there is one, or else the
+    // lowest index that is not used by a connected device.
+    fn get_device_index(&mut self, guid: &str) -> u32 {
+        if let Some(position) = self
+            .disconnected_devices
+            .iter()
+            .position(|device| device.guid == guid)
+        {
+            return self.disconnected_devices.remove(position).index;
+        }
+
+        let index = (0..)
+            .find(|index| {
+                self.devices
+                    .values()
+                    .all(|open_device| open_device.device.index != *index)
+            })
+            .expect("Expected unused device index.");
+        self.disconnected_devices
+            .retain(|device| device.index != index);
+        index
+    }
+
+    fn insert(&mut self, instance_id: i32, handle: DeviceHandle, guid: String) -> InputDevice {
+        let index = self.get_device_index(&guid);
+        info!(
+            "[GUI] Opened device {} with GUID {}: {}",
+            index,
+            guid,
+            handle.description()
+        );
+        let device = InputDevice { index, guid };
+        self.devices.insert(
+            instance_id,
+            OpenDevice {
+                handle,
+                device: device.clone(),
+            },
+        );
+        device
+    }
+
+    // SDL also sends an added event for every controller that was connected at startup, so
+    // controllers that are already open are skipped. Returns the device if it was opened.
+    pub fn open(
+        &mut self,
+        game_controller_subsystem: &GameControllerSubsystem,
+        joystick_subsystem: &JoystickSubsystem,
+        joystick_index: u32,
+    ) -> Option<InputDevice> {
+        let guid = joystick_subsystem
+            .device_guid(joystick_index)
+            .map(|guid| guid.string())
+            .unwrap_or_default();
+        match game_controller_subsystem.open(joystick_index) {
+            Ok(controller) => {
+                if self.devices.contains_key(&controller.instance_id()) {
+                    return None;
+                }
+                Some(self.insert(
+                    controller.instance_id(),
+                    DeviceHandle::Controller(controller),
+                    guid,
+                ))
+            }
+            Err(err) => {
+                error!("[GUI] Failed to open controller: {}", err);
+                None
+            }
+        }
+    }
+
+    // SDL sends a joystick added event for game controllers too, but those are opened through
+    // `open` instead.
+    pub fn open_joystick(
+        &mut self,
+        game_controller_subsystem: &GameControllerSubsystem,
+        joystick_subsystem: &JoystickSubsystem,
+        joystick_index: u32,
+    ) -> Option<InputDevice> {
+        if game_controller_subsystem.is_game_controller(joystick_index) {
+            return None;
+        }
+        match joystick_subsystem.open(joystick_index) {
+            Ok(joystick) => {
+                if self.devices.contains_key(&joystick.instance_id()) {
+                    return None;
+                }
+                let guid = joystick.guid().string();
+                Some(self.insert(
+                    joystick.instance_id(),
+                    DeviceHandle::Joystick(joystick),
+                    guid,
+                ))
+            }
+            Err(err) => {
+                error!("[GUI] Failed to open joystick: {}", err);
+                None
+            }
+        }
+    }
+
+    // Returns the device of the closed controller or joystick if it was open. SDL sends both a
+    // controller and a joystick removed event for game controllers, so the second one is ignored.
+    pub fn close(&mut self, instance_id: i32) -> Option<InputDevice> {
+        let open_device = self.devices.remove(&instance_id)?;
+        info!(
+            "[GUI] Closed device {}: {}",
+            open_device.device.index,
+            open_device.handle.description()
+        );
+        self.disconnected_devices.push(open_device.device.clone());
+        if self.disconnected_devices.len() > MAX_DISCONNECTED_DEVICES {
+            self.disconnected_devices.remove(0);
+        }
+        Some(open_device.device)
+    }
+
+    pub fn get_device(&self, instance_id: i32) -> Option<&InputDevice> {
+        self.devices
+            .get(&instance_id)
+            .filter(|open_device| matches!(open_device.handle, DeviceHandle::Controller(_)))
+            .map(|open_device| &open_device.device)
+    }
+
+    // Game controllers also send joystick events, so only raw joysticks are returned to avoid
+    // handling their input twice.
+    pub fn get_joystick_device(&self, instance_id: i32) -> Option<&InputDevice> {
+        self.devices
+            .get(&instance_id)
+            .filter(|open_device| matches!(open_device.handle, DeviceHandle::Joystick(_)))
+            .map(|open_device| &open_device.device)
+    }
+}
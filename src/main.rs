@@ -1,18 +1,56 @@
 mod config;
+mod filter;
 mod graphics;
+mod input;
+mod movie;
+mod palette;
+mod recording;
+mod regression;
+mod remap;
+mod renderer;
+mod rewind;
+mod screenshot;
 
 use clap::{App, Arg};
 use log::{error, info, warn};
 use neso::Nes;
-use sdl2::audio::AudioSpecDesired;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::controller::Button;
 use sdl2::event::Event;
-use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::joystick::HatState;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
+use sdl2::video::FullscreenType;
 use simplelog::{CombinedLogger, Level, LevelFilter, TermLogger};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
-use std::{error, fmt, fs, process, ptr, result, slice, thread};
+use std::{error, fmt, fs, mem, process, result, slice, thread};
 
-const SPEEDS: [f32; 9] = [
+// Time to spend stepping frames each loop while fast forwarding uncapped, which leaves time to draw
+// the last frame and handle events.
+const UNCAPPED_FAST_FORWARD_TIME: Duration = Duration::from_millis(14);
+
+// Maximum relative change to the sample rate when adjusting it to the audio queue.
+const MAX_RATE_DELTA: f32 = 0.005;
+
+// Maximum number of frames to step each loop while filling the audio queue with audio sync, which
+// leaves time to handle events if the emulator cannot keep up.
+const MAX_AUDIO_SYNC_FRAMES: u32 = 8;
+
+// Maximum time to wait for the audio queue to drain with audio sync in case the audio device stopped
+// playing.
+const MAX_AUDIO_SYNC_WAIT: Duration = Duration::from_millis(100);
+
+// Time that `frame_advance` has to be held before it steps a frame every loop.
+const FRAME_ADVANCE_REPEAT_DELAY: Duration = Duration::from_millis(500);
+
+// The configured speed is added to these if it is not one of them.
+const SPEEDS: [f32; 11] = [
+    1.0 / 8.0,
+    1.0 / 4.0,
     1.0 / 2.0,
     1.0 / 1.75,
     1.0 / 1.5,
@@ -82,11 +120,110 @@ struct EmulatorState {
     is_muted: bool,
     is_paused: bool,
     is_running: bool,
+    is_headless: bool,
+    is_rewinding: bool,
+    is_fast_forward_held: bool,
+    frame_advance_held_since: Option<Instant>,
+    is_frame_advance_pending: bool,
+    // `a` and `b` stay pressed while their standard binding is held, even if their turbo binding
+    // is also held.
+    held_turbo_buttons: [[bool; config::TURBO_BUTTON_COUNT]; config::PORT_COUNT],
+    held_standard_turbo_buttons: [[bool; config::TURBO_BUTTON_COUNT]; config::PORT_COUNT],
+    axis_directions: HashMap<(u32, input::AnalogAxis), config::Direction>,
+    remap_session: Option<remap::RemapSession>,
+    // Ports whose controller was disconnected while the emulator was running. Pausing or
+    // unpausing the emulator in the meantime clears them.
+    ports_awaiting_reconnect: Vec<usize>,
+    // Ordered from the least to the most recently pressed.
+    held_controller_buttons: Vec<(Option<u32>, Button)>,
+    // The keybinding that each held key or button resolved to, keyed by its device index and the
+    // key or button on its own.
+    pressed_keybindings: HashMap<(Option<u32>, config::KeybindingValue), config::KeybindingValue>,
+    hat_directions: HashMap<(u32, u8), &'static [config::HatDirection]>,
+    is_fullscreen: bool,
     debug_enabled: bool,
+    speeds: Vec<f32>,
     speed_index: usize,
+    save_state_slot: usize,
+    rewind_buffer: rewind::RewindBuffer,
+    movie_recorder: Option<movie::MovieRecorder>,
+    movie_player: Option<movie::MoviePlayer>,
+    frame: u64,
+    scheduled_screenshot: Option<(u64, PathBuf)>,
+    pending_debug_screenshot: Option<PathBuf>,
+    av_recorder: Option<recording::AvRecorder>,
+    audio_dumper: Option<recording::WavWriter>,
+    palette: Option<palette::Palette>,
+    filter: filter::Filter,
+    sample_freq: f32,
+    frame_budget: f64,
+    volume: u32,
 }
 
 impl EmulatorState {
+    fn new<T>(config: config::Config, rom_path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let mut speeds = SPEEDS.to_vec();
+        if !speeds.contains(&config.speed) {
+            speeds.push(config.speed);
+            speeds.sort_by(|a, b| a.partial_cmp(b).expect("Expected speed to be a number."));
+        }
+        let speed_index = speeds
+            .iter()
+            .position(|speed| *speed == config.speed)
+            .expect("Expected configured speed to exist.");
+        EmulatorState {
+            nes: Nes::default(),
+            is_fullscreen: config.video_config.fullscreen,
+            filter: config.video_config.filter,
+            sample_freq: config.audio_config.sample_rate as f32,
+            volume: config.audio_config.volume,
+            rewind_buffer: rewind::RewindBuffer::new(
+                config.rewind_buffer_size,
+                config.rewind_interval,
+            ),
+            config,
+            rom_path: rom_path.into(),
+            is_muted: false,
+            is_paused: false,
+            is_running: true,
+            is_headless: false,
+            is_rewinding: false,
+            is_fast_forward_held: false,
+            frame_advance_held_since: None,
+            is_frame_advance_pending: false,
+            held_turbo_buttons: [[false; config::TURBO_BUTTON_COUNT]; config::PORT_COUNT],
+            held_standard_turbo_buttons: [[false; config::TURBO_BUTTON_COUNT]; config::PORT_COUNT],
+            axis_directions: HashMap::new(),
+            hat_directions: HashMap::new(),
+            remap_session: None,
+            ports_awaiting_reconnect: Vec::new(),
+            held_controller_buttons: Vec::new(),
+            pressed_keybindings: HashMap::new(),
+            debug_enabled: false,
+            speeds,
+            speed_index,
+            save_state_slot: 0,
+            movie_recorder: None,
+            movie_player: None,
+            frame: 0,
+            scheduled_screenshot: None,
+            pending_debug_screenshot: None,
+            av_recorder: None,
+            audio_dumper: None,
+            palette: None,
+            frame_budget: 0.0,
+        }
+    }
+
+    fn load_rom(&mut self) -> Result<()> {
+        let rom = fs::read(&self.rom_path).map_err(|err| Error::new("reading ROM", &err))?;
+        self.nes.load_rom(&rom);
+        Ok(())
+    }
+
     fn toggle_muted(&mut self) {
         self.is_muted = !self.is_muted;
         info!("[GUI] Is muted: {}.", self.is_muted);
@@ -94,119 +231,1007 @@ impl EmulatorState {
 
     fn toggle_paused(&mut self) {
         self.is_paused = !self.is_paused;
+        self.ports_awaiting_reconnect.clear();
         info!("[GUI] Is paused: {}.", self.is_paused);
     }
 
+    // The emulator is paused and every button is released while remapping so that the captured
+    // inputs do not reach the game.
+    fn start_remap(&mut self, keybinding_value: config::KeybindingValue) {
+        for port in 0..config::PORT_COUNT {
+            self.release_all_buttons(port);
+        }
+        self.remap_session = Some(remap::RemapSession::new(
+            &self.config,
+            keybinding_value,
+            self.is_paused,
+        ));
+        if !self.is_paused {
+            self.toggle_paused();
+        }
+    }
+
+    fn finish_remap(&mut self) -> Result<()> {
+        let remap_session = match self.remap_session.take() {
+            Some(remap_session) => remap_session,
+            None => return Ok(()),
+        };
+        if remap_session.is_canceled() {
+            remap_session.restore_bindings(&mut self.config);
+            info!("[GUI] Remapping was canceled.");
+        } else if remap_session.is_changed() {
+            self.config.write_config()?;
+            info!(
+                "[GUI] Wrote keybindings to {}.",
+                self.config.config_path.display()
+            );
+        } else {
+            info!("[GUI] No keybindings were changed.");
+        }
+        if !remap_session.was_paused() && self.is_paused {
+            self.toggle_paused();
+        }
+        Ok(())
+    }
+
+    fn set_rewinding(&mut self, is_rewinding: bool) {
+        if self.is_rewinding != is_rewinding {
+            self.is_rewinding = is_rewinding;
+            info!("[GUI] Is rewinding: {}.", self.is_rewinding);
+        }
+    }
+
+    fn set_fast_forward_held(&mut self, is_fast_forward_held: bool) {
+        if self.is_fast_forward_held != is_fast_forward_held {
+            self.is_fast_forward_held = is_fast_forward_held;
+            info!("[GUI] Is fast forwarding: {}.", self.is_fast_forward_held);
+        }
+    }
+
+    fn start_frame_advance(&mut self) {
+        // Key repeats are ignored since holding the keybinding is handled by the main loop.
+        if self.frame_advance_held_since.is_some() {
+            return;
+        }
+
+        if !self.is_paused {
+            self.toggle_paused();
+        }
+        self.frame_advance_held_since = Some(Instant::now());
+        self.is_frame_advance_pending = true;
+    }
+
+    // Steps one frame when `frame_advance` is pressed and then one frame every loop after it has
+    // been held for `FRAME_ADVANCE_REPEAT_DELAY`. The delay is measured in real time so that it
+    // does not depend on how long each loop takes at the current speed.
+    fn get_frame_advance_frames(&mut self) -> u32 {
+        if mem::replace(&mut self.is_frame_advance_pending, false) {
+            return 1;
+        }
+        match self.frame_advance_held_since {
+            Some(held_since) if held_since.elapsed() >= FRAME_ADVANCE_REPEAT_DELAY => 1,
+            _ => 0,
+        }
+    }
+
+    // Rewinding takes priority over fast forwarding.
+    fn is_fast_forwarding(&self) -> bool {
+        self.is_fast_forward_held && !self.is_rewinding
+    }
+
+    fn is_movie_active(&self) -> bool {
+        self.movie_recorder.is_some() || self.movie_player.is_some()
+    }
+
+    // Movies start from a clean save, and headless runs have to be reproducible, so neither reads
+    // or writes the save file.
+    fn uses_save_file(&self) -> bool {
+        !self.is_movie_active() && !self.is_headless
+    }
+
     fn stop(&mut self) -> Result<()> {
-        self.save()?;
+        if let Some(movie_recorder) = &mut self.movie_recorder {
+            movie_recorder.flush()?;
+        }
+        self.stop_av_recording()?;
+        self.stop_audio_dump()?;
+        if self.uses_save_file() {
+            self.save()?;
+        }
         self.is_running = false;
         Ok(())
     }
 
+    fn speed(&self) -> f32 {
+        self.speeds[self.speed_index]
+    }
+
     fn increase_speed(&mut self) {
-        if self.speed_index < SPEEDS.len() - 1 {
+        if self.speed_index < self.speeds.len() - 1 {
             self.speed_index += 1;
         }
-        info!("[GUI] Speed set to: {:.2}.", SPEEDS[self.speed_index]);
-        self.reset_sample_freq();
+        info!("[GUI] Speed set to: {:.3}.", self.speed());
+        self.reset_sample_freq();
+    }
+
+    fn decrease_speed(&mut self) {
+        if self.speed_index > 0 {
+            self.speed_index -= 1;
+        }
+        info!("[GUI] Speed set to: {:.3}.", self.speed());
+        self.reset_sample_freq();
+    }
+
+    fn select_save_state_slot(&mut self, slot: usize) -> Result<()> {
+        self.save_state_slot = slot;
+        info!("[GUI] Save state slot set to: {}.", self.save_state_slot);
+        self.save_save_state_slot()
+    }
+
+    fn next_save_state_slot(&mut self) -> Result<()> {
+        self.select_save_state_slot((self.save_state_slot + 1) % config::SAVE_STATE_SLOTS)
+    }
+
+    fn prev_save_state_slot(&mut self) -> Result<()> {
+        self.select_save_state_slot(
+            (self.save_state_slot + config::SAVE_STATE_SLOTS - 1) % config::SAVE_STATE_SLOTS,
+        )
+    }
+
+    fn image_buffer(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.nes.image_buffer(), 240 * 256 * 4) }
+    }
+
+    fn get_palette_image(&self) -> Cow<'_, [u8]> {
+        match &self.palette {
+            Some(palette) => {
+                let mut palette_image = vec![0; 240 * 256 * 4];
+                palette.apply(self.image_buffer(), &mut palette_image);
+                Cow::Owned(palette_image)
+            }
+            None => Cow::Borrowed(self.image_buffer()),
+        }
+    }
+
+    // Crops the overscan from the image buffer and applies the custom palette.
+    fn get_output_image(&self) -> Vec<u8> {
+        let overscan = self.config.video_config.overscan;
+        let row_start = overscan.left as usize * 4;
+        let row_end = row_start + overscan.width() as usize * 4;
+        self.get_palette_image()
+            .chunks(256 * 4)
+            .skip(overscan.top as usize)
+            .take(overscan.height() as usize)
+            .flat_map(|row| row[row_start..row_end].iter().cloned())
+            .collect()
+    }
+
+    fn cycle_filter(&mut self) {
+        self.filter = self.filter.next();
+        info!("[GUI] Filter set to: {}.", self.filter.name());
+    }
+
+    fn take_screenshot(&mut self) -> Result<()> {
+        fs::create_dir_all(&self.config.data_path)
+            .map_err(|err| Error::new("creating data directory: {}", &err))?;
+        let screenshot_file_path = self.config.get_screenshot_file(&self.rom_path);
+        let overscan = self.config.video_config.overscan;
+        screenshot::write_png(
+            &screenshot_file_path,
+            overscan.width(),
+            overscan.height(),
+            &self.get_output_image(),
+        )?;
+        if self.debug_enabled {
+            self.pending_debug_screenshot = Some(screenshot_file_path.with_extension("debug.png"));
+        }
+        Ok(())
+    }
+
+    fn audio_buffer(&self) -> &[f32] {
+        unsafe { slice::from_raw_parts(self.nes.audio_buffer(), self.nes.audio_buffer_len()) }
+    }
+
+    fn start_av_recording<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let overscan = self.config.video_config.overscan;
+        self.av_recorder = Some(recording::AvRecorder::create(
+            path,
+            overscan.width() as usize,
+            overscan.height() as usize,
+            self.config.audio_config.sample_rate,
+        )?);
+        info!("[GUI] Is recording: true.");
+        Ok(())
+    }
+
+    fn stop_av_recording(&mut self) -> Result<()> {
+        if let Some(av_recorder) = self.av_recorder.take() {
+            av_recorder.finish()?;
+            info!("[GUI] Is recording: false.");
+        }
+        Ok(())
+    }
+
+    fn toggle_av_recording(&mut self) -> Result<()> {
+        if self.av_recorder.is_some() {
+            self.stop_av_recording()
+        } else {
+            fs::create_dir_all(&self.config.data_path)
+                .map_err(|err| Error::new("creating data directory: {}", &err))?;
+            let recording_file_path = self.config.get_recording_file(&self.rom_path);
+            self.start_av_recording(recording_file_path)
+        }
+    }
+
+    fn start_audio_dump<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        self.audio_dumper = Some(recording::WavWriter::create(
+            path,
+            self.config.audio_config.sample_rate,
+        )?);
+        info!("[GUI] Is dumping audio: true.");
+        Ok(())
+    }
+
+    fn stop_audio_dump(&mut self) -> Result<()> {
+        if let Some(audio_dumper) = self.audio_dumper.take() {
+            audio_dumper.finish()?;
+            info!("[GUI] Is dumping audio: false.");
+        }
+        Ok(())
+    }
+
+    fn toggle_audio_dump(&mut self) -> Result<()> {
+        if self.audio_dumper.is_some() {
+            self.stop_audio_dump()
+        } else {
+            fs::create_dir_all(&self.config.data_path)
+                .map_err(|err| Error::new("creating data directory: {}", &err))?;
+            let audio_dump_file_path = self.config.get_audio_dump_file(&self.rom_path);
+            self.start_audio_dump(audio_dump_file_path)
+        }
+    }
+
+    fn queue_audio(&mut self, audio_queue: &AudioQueue<f32>) -> Result<()> {
+        let volume = self.volume as f32 / 100.0;
+        let samples = self
+            .audio_buffer()
+            .iter()
+            .map(|sample| sample * volume)
+            .collect::<Vec<f32>>();
+        audio_queue.queue(&samples);
+        if let Some(audio_dumper) = &mut self.audio_dumper {
+            audio_dumper.write_samples(&samples)?;
+            if audio_dumper.is_full() {
+                warn!("Stopping the audio dump since it reached the size limit of a WAV file.");
+                self.stop_audio_dump()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn increase_volume(&mut self) {
+        self.volume = (self.volume + 10).min(100);
+        info!("[GUI] Volume set to: {}%.", self.volume);
+    }
+
+    fn decrease_volume(&mut self) {
+        self.volume = self.volume.saturating_sub(10);
+        info!("[GUI] Volume set to: {}%.", self.volume);
+    }
+
+    fn press_button(&mut self, port: usize, button_index: u8) {
+        self.nes.press_button(port, button_index);
+        if let Some(movie_recorder) = &mut self.movie_recorder {
+            movie_recorder.press_button(port, button_index);
+        }
+    }
+
+    fn release_button(&mut self, port: usize, button_index: u8) {
+        self.nes.release_button(port, button_index);
+        if let Some(movie_recorder) = &mut self.movie_recorder {
+            movie_recorder.release_button(port, button_index);
+        }
+    }
+
+    fn release_all_buttons(&mut self, port: usize) {
+        for button_index in 0..config::BUTTON_COUNT {
+            self.release_button(port, button_index as u8);
+        }
+        self.held_turbo_buttons[port] = [false; config::TURBO_BUTTON_COUNT];
+        self.held_standard_turbo_buttons[port] = [false; config::TURBO_BUTTON_COUNT];
+    }
+
+    fn reset(&mut self) {
+        self.nes.reset();
+        if let Some(movie_recorder) = &mut self.movie_recorder {
+            movie_recorder.reset();
+        }
+    }
+
+    fn get_mapped_buttons(
+        &self,
+        keybinding_value: config::KeybindingValue,
+        device: Option<&input::InputDevice>,
+    ) -> Vec<(usize, u8)> {
+        // Controller input is ignored during movie playback since the movie drives the buttons.
+        if self.movie_player.is_some() {
+            return Vec::new();
+        }
+
+        self.config
+            .controller_configs
+            .iter()
+            .enumerate()
+            .filter(|(_, controller_config)| controller_config.matches_device(device))
+            .filter_map(|(port, controller_config)| {
+                controller_config
+                    .keybinding_map
+                    .get(&keybinding_value)
+                    .map(|index| (port, *index as u8))
+            })
+            .collect()
+    }
+
+    fn handle_button_press(
+        &mut self,
+        keybinding_value: config::KeybindingValue,
+        device: Option<&input::InputDevice>,
+    ) -> Result<()> {
+        if let Some(remap_session) = &mut self.remap_session {
+            remap_session.handle_button_press(keybinding_value, device);
+            return Ok(());
+        }
+
+        for (port, button_index) in self.get_mapped_buttons(keybinding_value, device) {
+            match (button_index as usize).checked_sub(config::BUTTON_COUNT) {
+                Some(turbo_index) => self.held_turbo_buttons[port][turbo_index] = true,
+                None => {
+                    let index = button_index as usize;
+                    if index < config::TURBO_BUTTON_COUNT {
+                        self.held_standard_turbo_buttons[port][index] = true;
+                    }
+                    self.press_button(port, button_index);
+                }
+            }
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .mute
+            .contains(&keybinding_value)
+        {
+            self.toggle_muted();
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .pause
+            .contains(&keybinding_value)
+        {
+            self.toggle_paused();
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .reset
+            .contains(&keybinding_value)
+        {
+            self.reset();
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .exit
+            .contains(&keybinding_value)
+        {
+            self.stop()?;
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .save_state
+            .contains(&keybinding_value)
+        {
+            self.save_state()?;
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .load_state
+            .contains(&keybinding_value)
+        {
+            self.load_state()?;
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .increase_speed
+            .contains(&keybinding_value)
+        {
+            self.increase_speed();
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .decrease_speed
+            .contains(&keybinding_value)
+        {
+            self.decrease_speed();
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .rewind
+            .contains(&keybinding_value)
+        {
+            if self.is_movie_active() {
+                warn!("Cannot rewind while a movie is active.");
+            } else {
+                self.set_rewinding(true);
+            }
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .fast_forward
+            .contains(&keybinding_value)
+        {
+            self.set_fast_forward_held(true);
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .frame_advance
+            .contains(&keybinding_value)
+        {
+            self.start_frame_advance();
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .screenshot
+            .contains(&keybinding_value)
+        {
+            self.take_screenshot()?;
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .record_av
+            .contains(&keybinding_value)
+        {
+            self.toggle_av_recording()?;
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .dump_audio
+            .contains(&keybinding_value)
+        {
+            self.toggle_audio_dump()?;
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .toggle_fullscreen
+            .contains(&keybinding_value)
+        {
+            self.toggle_fullscreen();
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .cycle_filter
+            .contains(&keybinding_value)
+        {
+            self.cycle_filter();
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .volume_up
+            .contains(&keybinding_value)
+        {
+            self.increase_volume();
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .volume_down
+            .contains(&keybinding_value)
+        {
+            self.decrease_volume();
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .remap
+            .contains(&keybinding_value)
+        {
+            self.start_remap(keybinding_value);
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .next_slot
+            .contains(&keybinding_value)
+        {
+            self.next_save_state_slot()?;
+        }
+
+        if self
+            .config
+            .keybindings_config
+            .prev_slot
+            .contains(&keybinding_value)
+        {
+            self.prev_save_state_slot()?;
+        }
+
+        if let Some(slot) = self
+            .config
+            .keybindings_config
+            .select_slot
+            .iter()
+            .position(|keybindings| keybindings.contains(&keybinding_value))
+        {
+            self.select_save_state_slot(slot)?;
+        }
+
+        Ok(())
+    }
+
+    // An axis is pressed in a direction once it moves past `axis_deadzone` percent of its range, and
+    // is released once it moves back within `axis_deadzone - axis_hysteresis` percent so that a
+    // stick resting near the deadzone does not repeatedly press and release.
+    fn handle_axis_motion(
+        &mut self,
+        axis: input::AnalogAxis,
+        value: i16,
+        device: &input::InputDevice,
+    ) -> Result<()> {
+        let press_threshold = i32::from(i16::MAX) * self.config.axis_deadzone as i32 / 100;
+        let release_threshold = i32::from(i16::MAX)
+            * (self.config.axis_deadzone - self.config.axis_hysteresis) as i32
+            / 100;
+        let value = i32::from(value);
+        let curr_direction = self.axis_directions.get(&(device.index, axis)).cloned();
+        let next_direction = match curr_direction {
+            Some(config::Direction::Positive) if value > release_threshold => curr_direction,
+            Some(config::Direction::Negative) if value < -release_threshold => curr_direction,
+            _ if value > press_threshold => Some(config::Direction::Positive),
+            _ if value < -press_threshold => Some(config::Direction::Negative),
+            _ => None,
+        };
+
+        if curr_direction == next_direction {
+            return Ok(());
+        }
+        if let Some(direction) = curr_direction {
+            self.axis_directions.remove(&(device.index, axis));
+            self.handle_button_release(axis.keybinding_value(direction), Some(device))?;
+        }
+        if let Some(direction) = next_direction {
+            self.axis_directions.insert((device.index, axis), direction);
+            self.handle_button_press(axis.keybinding_value(direction), Some(device))?;
+        }
+        Ok(())
+    }
+
+    // Releases the directions that the hat moved away from before pressing the new ones.
+    fn handle_hat_motion(
+        &mut self,
+        hat_index: u8,
+        state: HatState,
+        device: &input::InputDevice,
+    ) -> Result<()> {
+        let next_directions = config::HatDirection::from_hat_state(state);
+        let curr_directions = self
+            .hat_directions
+            .insert((device.index, hat_index), next_directions)
+            .unwrap_or(&[]);
+        for direction in curr_directions {
+            if !next_directions.contains(direction) {
+                self.handle_button_release(
+                    config::KeybindingValue::JoystickHatValue(hat_index, *direction),
+                    Some(device),
+                )?;
+            }
+        }
+        for direction in next_directions {
+            if !curr_directions.contains(direction) {
+                self.handle_button_press(
+                    config::KeybindingValue::JoystickHatValue(hat_index, *direction),
+                    Some(device),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // Unpauses the emulator once every port that paused it when its controller was disconnected is
+    // driven by a connected controller again.
+    fn handle_controller_added(&mut self, device: &input::InputDevice) {
+        if self.ports_awaiting_reconnect.is_empty() {
+            return;
+        }
+
+        let controller_configs = &self.config.controller_configs;
+        self.ports_awaiting_reconnect
+            .retain(|port| !controller_configs[*port].matches_device(Some(device)));
+        if self.ports_awaiting_reconnect.is_empty()
+            && self.is_paused
+            && self.remap_session.is_none()
+        {
+            info!("[GUI] Controller {} was reconnected.", device.index);
+            self.toggle_paused();
+        }
+    }
+
+    // Releases the buttons of every port that the controller was driving and pauses the emulator
+    // until a controller for those ports is reconnected. If the emulator was already paused, it is
+    // only unpaused on a reconnect if a disconnect paused it.
+    fn handle_controller_removed(&mut self, device: &input::InputDevice) {
+        self.axis_directions
+            .retain(|(device_index, _), _| *device_index != device.index);
+        self.hat_directions
+            .retain(|(device_index, _), _| *device_index != device.index);
+        self.held_controller_buttons
+            .retain(|(device_index, _)| *device_index != Some(device.index));
+        self.pressed_keybindings
+            .retain(|(device_index, _), _| *device_index != Some(device.index));
+        let ports = self
+            .config
+            .controller_configs
+            .iter()
+            .enumerate()
+            .filter(|(_, controller_config)| {
+                controller_config.uses_controller()
+                    && controller_config.matches_device(Some(device))
+            })
+            .map(|(port, _)| port)
+            .collect::<Vec<usize>>();
+
+        for port in &ports {
+            self.release_all_buttons(*port);
+        }
+
+        if ports.is_empty() {
+            return;
+        }
+        warn!(
+            "Controller {} for port {} was disconnected.",
+            device.index,
+            ports[0] + 1
+        );
+        if !self.is_paused {
+            self.toggle_paused();
+            self.ports_awaiting_reconnect = ports;
+        } else if !self.ports_awaiting_reconnect.is_empty() {
+            for port in ports {
+                if !self.ports_awaiting_reconnect.contains(&port) {
+                    self.ports_awaiting_reconnect.push(port);
+                }
+            }
+        }
     }
 
-    fn decrease_speed(&mut self) {
-        if self.speed_index > 0 {
-            self.speed_index -= 1;
+    fn handle_button_release(
+        &mut self,
+        keybinding_value: config::KeybindingValue,
+        device: Option<&input::InputDevice>,
+    ) -> Result<()> {
+        if let Some(remap_session) = &mut self.remap_session {
+            remap_session.handle_button_release(&mut self.config, keybinding_value);
+            if remap_session.is_finished() {
+                self.finish_remap()?;
+            }
+            return Ok(());
         }
-        info!("[GUI] Speed set to: {:.2}.", SPEEDS[self.speed_index]);
-        self.reset_sample_freq();
-    }
 
-    fn handle_button_press(&mut self, keybinding_value: config::KeybindingValue) -> Result<()> {
-        for (port, controller_config) in self.config.controller_configs.iter().enumerate() {
-            if let Some(index) = controller_config.keybinding_map.get(&keybinding_value) {
-                self.nes.press_button(port, *index as u8);
+        for (port, button_index) in self.get_mapped_buttons(keybinding_value, device) {
+            match (button_index as usize).checked_sub(config::BUTTON_COUNT) {
+                Some(turbo_index) => {
+                    self.held_turbo_buttons[port][turbo_index] = false;
+                    if !self.held_standard_turbo_buttons[port][turbo_index] {
+                        self.release_button(port, turbo_index as u8);
+                    }
+                }
+                None => {
+                    let index = button_index as usize;
+                    if index < config::TURBO_BUTTON_COUNT {
+                        self.held_standard_turbo_buttons[port][index] = false;
+                    }
+                    if index >= config::TURBO_BUTTON_COUNT || !self.held_turbo_buttons[port][index]
+                    {
+                        self.release_button(port, button_index);
+                    }
+                }
             }
         }
 
         if self
             .config
             .keybindings_config
-            .mute
+            .rewind
             .contains(&keybinding_value)
         {
-            self.toggle_muted();
+            self.set_rewinding(false);
         }
 
         if self
             .config
             .keybindings_config
-            .pause
+            .fast_forward
             .contains(&keybinding_value)
         {
-            self.toggle_paused();
+            self.set_fast_forward_held(false);
         }
 
         if self
             .config
             .keybindings_config
-            .reset
+            .frame_advance
             .contains(&keybinding_value)
         {
-            self.nes.reset();
+            self.frame_advance_held_since = None;
         }
 
-        if self
-            .config
-            .keybindings_config
-            .exit
-            .contains(&keybinding_value)
-        {
-            self.stop()?;
+        Ok(())
+    }
+
+    fn is_bound(&self, keybinding_value: config::KeybindingValue) -> bool {
+        self.config.keybindings_config.contains(&keybinding_value)
+            || self
+                .config
+                .controller_configs
+                .iter()
+                .any(|controller_config| {
+                    controller_config
+                        .keybinding_map
+                        .contains_key(&keybinding_value)
+                })
+    }
+
+    // Returns the first bound keybinding out of the candidates, which are ordered from the most
+    // specific to the least, or else the key or button on its own. While remapping, the most
+    // specific candidate is returned so that it can be bound.
+    fn resolve_keybinding(
+        &self,
+        candidates: Vec<config::KeybindingValue>,
+        keybinding_value: config::KeybindingValue,
+    ) -> config::KeybindingValue {
+        if self.remap_session.is_some() {
+            return candidates.first().cloned().unwrap_or(keybinding_value);
         }
+        candidates
+            .into_iter()
+            .find(|candidate| self.is_bound(*candidate))
+            .unwrap_or(keybinding_value)
+    }
 
-        if self
-            .config
-            .keybindings_config
-            .save_state
-            .contains(&keybinding_value)
+    // Key repeats keep the keybinding that the first press resolved to, and the same keybinding is
+    // released with the key or button even if the modifiers or held buttons changed in between.
+    fn press_resolved_keybinding(
+        &mut self,
+        keybinding_value: config::KeybindingValue,
+        candidates: Vec<config::KeybindingValue>,
+        device: Option<&input::InputDevice>,
+    ) -> Result<()> {
+        let key = (device.map(|device| device.index), keybinding_value);
+        let resolved_value = match self.pressed_keybindings.get(&key) {
+            Some(resolved_value) => *resolved_value,
+            None => self.resolve_keybinding(candidates, keybinding_value),
+        };
+        self.pressed_keybindings.insert(key, resolved_value);
+        self.handle_button_press(resolved_value, device)
+    }
+
+    fn release_resolved_keybinding(
+        &mut self,
+        keybinding_value: config::KeybindingValue,
+        device: Option<&input::InputDevice>,
+    ) -> Result<()> {
+        let resolved_value = self
+            .pressed_keybindings
+            .remove(&(device.map(|device| device.index), keybinding_value))
+            .unwrap_or(keybinding_value);
+        self.handle_button_release(resolved_value, device)
+    }
+
+    fn handle_key_press(&mut self, keycode: Keycode, keymod: Mod) -> Result<()> {
+        let candidates = config::Modifiers::from_keymod(keymod, keycode)
+            .subsets()
+            .map(|modifiers| config::KeybindingValue::KeyComboValue(modifiers, keycode))
+            .collect();
+        self.press_resolved_keybinding(
+            config::KeybindingValue::KeycodeValue(keycode),
+            candidates,
+            None,
+        )
+    }
+
+    fn handle_key_release(&mut self, keycode: Keycode) -> Result<()> {
+        self.release_resolved_keybinding(config::KeybindingValue::KeycodeValue(keycode), None)
+    }
+
+    // A chord is pressed when the button is pressed while another button of the same controller is
+    // held. If several buttons are held, the chord with the most recently held one is preferred.
+    fn handle_controller_button_press(
+        &mut self,
+        button: Button,
+        device: Option<&input::InputDevice>,
+    ) -> Result<()> {
+        let device_index = device.map(|device| device.index);
+        let candidates = self
+            .held_controller_buttons
+            .iter()
+            .rev()
+            .filter(|(held_device_index, _)| *held_device_index == device_index)
+            .map(|(_, held_button)| config::KeybindingValue::ButtonChordValue(*held_button, button))
+            .collect();
+        if !self
+            .held_controller_buttons
+            .contains(&(device_index, button))
         {
-            self.save_state()?;
+            self.held_controller_buttons.push((device_index, button));
         }
+        self.press_resolved_keybinding(
+            config::KeybindingValue::ButtonValue(button),
+            candidates,
+            device,
+        )
+    }
 
-        if self
-            .config
-            .keybindings_config
-            .load_state
-            .contains(&keybinding_value)
-        {
-            self.load_state()?;
+    fn handle_controller_button_release(
+        &mut self,
+        button: Button,
+        device: Option<&input::InputDevice>,
+    ) -> Result<()> {
+        let device_index = device.map(|device| device.index);
+        self.held_controller_buttons
+            .retain(|held_button| *held_button != (device_index, button));
+        self.release_resolved_keybinding(config::KeybindingValue::ButtonValue(button), device)
+    }
+
+    // Held turbo buttons are pressed for the `turbo_rate` of their port in frames and then released
+    // for as many frames.
+    fn update_turbo_buttons(&mut self) {
+        for port in 0..config::PORT_COUNT {
+            let turbo_rate = self.config.controller_configs[port].turbo_rate;
+            let is_pressed = (self.frame / u64::from(turbo_rate)) & 1 == 0;
+            for turbo_index in 0..config::TURBO_BUTTON_COUNT {
+                if !self.held_turbo_buttons[port][turbo_index]
+                    || self.held_standard_turbo_buttons[port][turbo_index]
+                {
+                    continue;
+                }
+                if is_pressed {
+                    self.press_button(port, turbo_index as u8);
+                } else {
+                    self.release_button(port, turbo_index as u8);
+                }
+            }
         }
+    }
 
-        if self
-            .config
-            .keybindings_config
-            .increase_speed
-            .contains(&keybinding_value)
-        {
-            self.increase_speed();
+    fn step_frame(&mut self) -> Result<()> {
+        self.step_frame_with_snapshot(true)
+    }
+
+    // The extra frames of an uncapped fast forward do not take rewind snapshots, so that the
+    // rewind buffer fills at the normal rate instead of being evicted within seconds.
+    fn step_uncapped_fast_forward_frame(&mut self) -> Result<()> {
+        self.step_frame_with_snapshot(false)
+    }
+
+    fn step_frame_with_snapshot(&mut self, is_taking_snapshot: bool) -> Result<()> {
+        if self.is_rewinding {
+            if !self.rewind_buffer.rewind_frame(&mut self.nes)? {
+                return Ok(());
+            }
+            self.reset_sample_freq();
+            self.nes.step_frame();
+        } else {
+            self.update_turbo_buttons();
+            self.play_movie_frame()?;
+            self.nes.step_frame();
+            if let Some(movie_recorder) = &mut self.movie_recorder {
+                movie_recorder.record_frame()?;
+            }
+            if is_taking_snapshot {
+                self.rewind_buffer.record_frame(&self.nes)?;
+            }
+            self.frame += 1;
         }
 
-        if self
-            .config
-            .keybindings_config
-            .decrease_speed
-            .contains(&keybinding_value)
-        {
-            self.decrease_speed();
+        if let Some(mut av_recorder) = self.av_recorder.take() {
+            av_recorder.record_frame(
+                &self.get_output_image(),
+                self.audio_buffer(),
+                self.config.audio_config.sample_rate as f32 / self.sample_freq,
+            )?;
+            let is_full = av_recorder.is_full();
+            self.av_recorder = Some(av_recorder);
+            if is_full {
+                warn!("Stopping the recording since it reached the size limit of a WAV file.");
+                self.stop_av_recording()?;
+            }
         }
 
+        if let Some((frame, screenshot_file_path)) = &self.scheduled_screenshot {
+            if *frame == self.frame {
+                let overscan = self.config.video_config.overscan;
+                screenshot::write_png(
+                    screenshot_file_path,
+                    overscan.width(),
+                    overscan.height(),
+                    &self.get_output_image(),
+                )?;
+                self.scheduled_screenshot = None;
+            }
+        }
         Ok(())
     }
 
-    fn handle_button_release(&mut self, keybinding_value: config::KeybindingValue) {
-        for (port, controller_config) in self.config.controller_configs.iter().enumerate() {
-            if let Some(index) = controller_config.keybinding_map.get(&keybinding_value) {
-                self.nes.release_button(port, *index as u8);
+    fn play_movie_frame(&mut self) -> Result<()> {
+        let movie_frame = match &mut self.movie_player {
+            Some(movie_player) => movie_player.next_frame(),
+            None => return Ok(()),
+        };
+
+        let movie_frame = match movie_frame {
+            Some(movie_frame) => movie_frame,
+            None => {
+                info!("[GUI] Movie playback finished.");
+                self.movie_player = None;
+                return Ok(());
+            }
+        };
+
+        if movie_frame.commands & movie::HARD_RESET != 0 {
+            self.load_rom()?;
+            self.reset_sample_freq();
+        } else if movie_frame.commands & movie::SOFT_RESET != 0 {
+            self.nes.reset();
+        }
+
+        for (port, buttons) in movie_frame.buttons.iter().enumerate() {
+            for button_index in 0..8 {
+                if buttons & 1 << button_index != 0 {
+                    self.nes.press_button(port, button_index);
+                } else {
+                    self.nes.release_button(port, button_index);
+                }
             }
         }
+        Ok(())
     }
 
     fn save(&self) -> Result<()> {
@@ -243,7 +1268,9 @@ impl EmulatorState {
             .nes
             .save_state()
             .map_err(|err| Error::new("getting save state data", &err))?;
-        let save_state_file_path = self.config.get_save_state_file(&self.rom_path);
+        let save_state_file_path = self
+            .config
+            .get_save_state_file(&self.rom_path, self.save_state_slot);
         info!(
             "[GUI] Writing save state file at {:?}.",
             save_state_file_path
@@ -256,7 +1283,22 @@ impl EmulatorState {
     }
 
     fn load_state(&mut self) -> Result<()> {
-        let save_state_file_path = self.config.get_save_state_file(&self.rom_path);
+        if self.is_movie_active() {
+            warn!("Cannot load save state while a movie is active.");
+            return Ok(());
+        }
+
+        let mut save_state_file_path = self
+            .config
+            .get_save_state_file(&self.rom_path, self.save_state_slot);
+        // An existing save state without a slot number is loaded as slot 0 until slot 0 is saved.
+        if !save_state_file_path.exists() && self.save_state_slot == 0 {
+            let legacy_save_state_file_path =
+                self.config.get_legacy_save_state_file(&self.rom_path);
+            if legacy_save_state_file_path.exists() {
+                save_state_file_path = legacy_save_state_file_path;
+            }
+        }
         if save_state_file_path.exists() {
             info!(
                 "[GUI] Reading save state file at {:?}.",
@@ -268,28 +1310,236 @@ impl EmulatorState {
                 .load_state(&data)
                 .map_err(|err| Error::new("loading save state data", &err))?;
         } else {
-            warn!("No save state exists for this ROM.");
+            warn!(
+                "No save state exists for this ROM in slot {}.",
+                self.save_state_slot
+            );
         }
         self.reset_sample_freq();
         Ok(())
     }
 
+    fn save_save_state_slot(&self) -> Result<()> {
+        let save_state_slot_file_path = self.config.get_save_state_slot_file(&self.rom_path);
+        fs::create_dir_all(&self.config.data_path)
+            .map_err(|err| Error::new("creating data directory: {}", &err))?;
+        fs::write(save_state_slot_file_path, self.save_state_slot.to_string())
+            .map_err(|err| Error::new("writing save state slot", &err))?;
+        Ok(())
+    }
+
+    fn load_save_state_slot(&mut self) -> Result<()> {
+        let save_state_slot_file_path = self.config.get_save_state_slot_file(&self.rom_path);
+        if save_state_slot_file_path.exists() {
+            let data = fs::read_to_string(save_state_slot_file_path)
+                .map_err(|err| Error::new("reading save state slot", &err))?;
+            match data.trim().parse::<usize>() {
+                Ok(slot) if slot < config::SAVE_STATE_SLOTS => self.save_state_slot = slot,
+                _ => warn!("Invalid save state slot for this ROM: {}.", data.trim()),
+            }
+            info!("[GUI] Save state slot set to: {}.", self.save_state_slot);
+        }
+        Ok(())
+    }
+
+    // While fast forwarding, the main loop runs at normal speed and steps several frames per loop.
     fn mus_per_frame(&self) -> Duration {
-        Duration::from_micros((1.0 / SPEEDS[self.speed_index] / 60.0 * 1e6).round() as u64)
+        let speed = if self.is_fast_forwarding() {
+            1.0
+        } else {
+            self.speed()
+        };
+        Duration::from_micros((1.0 / speed / 60.0 * 1e6).round() as u64)
     }
 
     fn reset_sample_freq(&mut self) {
-        self.nes
-            .set_sample_freq(44_100.0 / SPEEDS[self.speed_index]);
+        self.sample_freq = self.config.audio_config.sample_rate as f32 / self.speed();
+        self.nes.set_sample_freq(self.sample_freq);
+    }
+
+    fn target_queued_samples(&self) -> f32 {
+        self.config.audio_config.latency as f32 * self.config.audio_config.sample_rate as f32
+            / 1000.0
+    }
+
+    fn queued_samples(audio_queue: &AudioQueue<f32>) -> f32 {
+        (audio_queue.size() as usize / mem::size_of::<f32>()) as f32
+    }
+
+    // Nudges the sample rate so that the audio queue converges to the latency target instead of
+    // slowly draining or growing when the audio device and frame pacing drift apart.
+    fn adjust_sample_freq(&mut self, audio_queue: &AudioQueue<f32>) {
+        let sample_rate = self.config.audio_config.sample_rate as f32;
+        let target_samples = self.target_queued_samples();
+        let queued_samples = EmulatorState::queued_samples(audio_queue);
+        // Drop the queued audio if it fell far behind, such as after the window was dragged.
+        if queued_samples > target_samples * 4.0 {
+            audio_queue.clear();
+        }
+        let error = ((target_samples - queued_samples) / target_samples).clamp(-1.0, 1.0);
+        self.sample_freq = sample_rate / self.speed() * (1.0 + error * MAX_RATE_DELTA);
+        self.nes.set_sample_freq(self.sample_freq);
+    }
+
+    // With audio sync, frames are stepped until the queued audio reaches the latency target, so the
+    // audio device's clock paces the emulator. Slower speeds generate more samples per frame, so
+    // fewer frames are stepped.
+    fn step_audio_synced_frames(&mut self, audio_queue: &AudioQueue<f32>) -> Result<()> {
+        for _ in 0..MAX_AUDIO_SYNC_FRAMES {
+            if EmulatorState::queued_samples(audio_queue) >= self.target_queued_samples() {
+                break;
+            }
+            self.step_frame()?;
+            self.queue_audio(audio_queue)?;
+        }
+        Ok(())
+    }
+
+    // Sleeps until the audio device plays the queued audio past the latency target.
+    fn wait_for_audio_queue(&self, audio_queue: &AudioQueue<f32>) {
+        let start = Instant::now();
+        let sample_rate = self.config.audio_config.sample_rate as f32;
+        while start.elapsed() < MAX_AUDIO_SYNC_WAIT {
+            let excess_samples =
+                EmulatorState::queued_samples(audio_queue) - self.target_queued_samples();
+            if excess_samples < 0.0 {
+                break;
+            }
+            let excess_time = Duration::from_secs_f32(excess_samples / sample_rate);
+            thread::sleep(excess_time.max(Duration::from_millis(1)));
+        }
+    }
+
+    // With video sync, the main loop runs at the display refresh rate, so frames are accumulated to
+    // run the emulator at the current speed.
+    // With audio sync, the frames are stepped in `step_audio_synced_frames` instead while audio is
+    // queued.
+    // An uncapped fast forward steps one frame here and the remaining frames in the main loop.
+    fn get_frames_to_step(&mut self, refresh_rate: i32) -> u32 {
+        if self.is_paused {
+            return self.get_frame_advance_frames();
+        }
+
+        let speed = if self.is_fast_forwarding() {
+            self.config.fast_forward_speed.max(1) as f32
+        } else {
+            self.speed()
+        };
+        if self.config.audio_config.sync != config::SyncMode::Video {
+            return if self.is_fast_forwarding() {
+                speed as u32
+            } else {
+                1
+            };
+        }
+
+        self.frame_budget += f64::from(speed) * 60.0 / f64::from(refresh_rate);
+        let frames = self.frame_budget.floor();
+        self.frame_budget -= frames;
+        frames as u32
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        self.is_fullscreen = !self.is_fullscreen;
+        info!("[GUI] Is fullscreen: {}.", self.is_fullscreen);
+    }
+
+    fn fullscreen_type(&self) -> FullscreenType {
+        if !self.is_fullscreen {
+            FullscreenType::Off
+        } else if self.config.video_config.fullscreen_desktop {
+            FullscreenType::Desktop
+        } else {
+            FullscreenType::True
+        }
+    }
+
+    fn main_view_dimensions(&self) -> (f32, f32) {
+        let video_config = &self.config.video_config;
+        let pixel_aspect_ratio = video_config.aspect_ratio.pixel_aspect_ratio();
+        (
+            video_config.overscan.width() as f32 * pixel_aspect_ratio,
+            video_config.overscan.height() as f32,
+        )
+    }
+
+    // The filtered texture is scaled up from the image buffer, so the overscan is scaled to match.
+    fn main_view_source_rect(&self) -> Rect {
+        let overscan = self.config.video_config.overscan;
+        let (scale_x, scale_y) = self.filter.scale();
+        Rect::new(
+            (overscan.left * scale_x) as i32,
+            (overscan.top * scale_y) as i32,
+            overscan.width() * scale_x,
+            overscan.height() * scale_y,
+        )
     }
 
     fn window_dimensions(&self) -> (u32, u32) {
         if self.debug_enabled {
             (1024, 736)
         } else {
-            (512, 480)
+            let (width, height) = self.main_view_dimensions();
+            let scale = self.config.video_config.scale as f32;
+            (
+                (width * scale).round() as u32,
+                (height * scale).round() as u32,
+            )
+        }
+    }
+
+    // Fits the main view in the given area while keeping its aspect ratio.
+    fn get_main_view_rect(&self, area_width: u32, area_height: u32) -> Rect {
+        let (width, height) = self.main_view_dimensions();
+        let mut scale = (area_width as f32 / width).min(area_height as f32 / height);
+        if self.config.video_config.integer_scaling {
+            scale = scale.floor().max(1.0);
+        }
+        let scaled_width = (width * scale).round() as u32;
+        let scaled_height = (height * scale).round() as u32;
+        Rect::new(
+            (area_width as i32 - scaled_width as i32) / 2,
+            (area_height as i32 - scaled_height as i32) / 2,
+            scaled_width,
+            scaled_height,
+        )
+    }
+}
+
+fn run_headless(state: &mut EmulatorState, frames: Option<u64>) -> Result<()> {
+    if frames.is_none() && state.movie_player.is_none() {
+        return Err(Error::from_description(
+            "running headless",
+            "Expected `--frames` or `--play-movie` to be set.",
+        ));
+    }
+
+    let mut frame = 0;
+    loop {
+        let is_done = match frames {
+            Some(frames) => frame >= frames,
+            None => match &state.movie_player {
+                Some(movie_player) => movie_player.is_finished(),
+                None => true,
+            },
+        };
+        if is_done {
+            break;
         }
+        state.step_frame()?;
+        frame += 1;
+    }
+
+    info!("[GUI] Ran {} frames.", frame);
+    state.stop()?;
+    // A movie can finish before the frame that the screenshot was scheduled for.
+    if let Some((frame, _)) = &state.scheduled_screenshot {
+        return Err(Error::from_description(
+            "running headless",
+            format!("Stopped before taking the screenshot at frame {}.", frame),
+        ));
     }
+    Ok(())
 }
 
 fn run() -> Result<()> {
@@ -313,7 +1563,7 @@ fn run() -> Result<()> {
             Arg::with_name("rom-path")
                 .help("Path to rom.")
                 .index(1)
-                .required(true),
+                .required_unless("test"),
         )
         .arg(
             Arg::with_name("config")
@@ -335,25 +1585,182 @@ fn run() -> Result<()> {
                 .long("frames")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("speed")
+                .help("Speed to start at as a ratio such as `1/8` or `0.5`.")
+                .long("speed")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("screenshot-at")
+                .help("Frame to take a screenshot at.")
+                .long("screenshot-at")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .help("Path to write the screenshot taken with `--screenshot-at` to.")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .requires("screenshot-at"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .help("Path to record video and audio to as `.y4m` and `.wav` files.")
+                .long("record")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dump-audio")
+                .help("Path to write the played audio to as a `.wav` file.")
+                .long("dump-audio")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("headless")
+                .help("Run without a window or audio device.")
+                .long("headless")
+                .conflicts_with_all(&["debug", "dump-audio"]),
+        )
+        .arg(
+            Arg::with_name("test")
+                .help("Path to a regression test manifest to run.")
+                .long("test")
+                .takes_value(true)
+                .conflicts_with_all(&["debug", "headless", "record-movie", "play-movie"]),
+        )
+        .arg(
+            Arg::with_name("record-movie")
+                .help("Path to record an FM2 input movie to.")
+                .long("record-movie")
+                .takes_value(true)
+                .conflicts_with("play-movie"),
+        )
+        .arg(
+            Arg::with_name("play-movie")
+                .help("Path to an FM2 input movie to play back.")
+                .long("play-movie")
+                .takes_value(true),
+        )
         .get_matches();
 
-    let mut state = EmulatorState {
-        nes: Nes::default(),
-        config: config::Config::parse_config(config::get_config_path(matches.value_of("config")))?,
-        rom_path: matches
+    if let Some(manifest_path) = matches.value_of("test") {
+        return regression::run_tests(
+            manifest_path,
+            config::get_config_path(matches.value_of("config")),
+        );
+    }
+
+    let mut config =
+        config::Config::parse_config(config::get_config_path(matches.value_of("config")))?;
+    if let Some(speed) = matches.value_of("speed") {
+        config.speed = config::parse_speed(speed).ok_or_else(|| {
+            Error::from_description(
+                "parsing speed",
+                "Expected `--speed` to be between 1/16 and 2, such as `1/8` or `0.5`.",
+            )
+        })?;
+    }
+    let mut state = EmulatorState::new(
+        config,
+        matches
             .value_of("rom-path")
-            .expect("Expected `rom-path` to exist.")
-            .to_owned(),
-        is_muted: false,
-        is_paused: matches.value_of("frames").is_some(),
-        is_running: true,
-        debug_enabled: matches.is_present("debug"),
-        speed_index: 4,
+            .expect("Expected `rom-path` to exist."),
+    );
+    state.is_paused = matches.value_of("frames").is_some();
+    if let Some(palette_file_path) = &state.config.video_config.palette {
+        let default_colors = unsafe { slice::from_raw_parts(state.nes.colors(), 64) };
+        state.palette = Some(palette::Palette::from_file(
+            palette_file_path,
+            default_colors,
+        )?);
+    }
+    state.debug_enabled = matches.is_present("debug");
+    if let Some(frame) = matches.value_of("screenshot-at") {
+        let frame = frame
+            .parse::<u64>()
+            .map_err(|err| Error::new("parsing screenshot frame", &err))?;
+        // The screenshot is taken after stepping a frame, so frame 0 would never be reached.
+        if frame == 0 {
+            return Err(Error::from_description(
+                "parsing screenshot frame",
+                "Expected `--screenshot-at` to be a positive integer.",
+            ));
+        }
+        let screenshot_file_path = match matches.value_of("output") {
+            Some(output) => PathBuf::from(output),
+            None => {
+                fs::create_dir_all(&state.config.data_path)
+                    .map_err(|err| Error::new("creating data directory: {}", &err))?;
+                state.config.get_screenshot_file(&state.rom_path)
+            }
+        };
+        state.scheduled_screenshot = Some((frame, screenshot_file_path));
+    }
+    if let Some(audio_dump_file_path) = matches.value_of("dump-audio") {
+        state.start_audio_dump(audio_dump_file_path)?;
+    }
+    if let Some(recording_file_path) = matches.value_of("record") {
+        state.start_av_recording(recording_file_path)?;
+    }
+    let rom_filename = Path::new(&state.rom_path)
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let rom_checksum = movie::rom_checksum(
+        &fs::read(&state.rom_path).map_err(|err| Error::new("reading ROM", &err))?,
+    );
+    if let Some(movie_path) = matches.value_of("record-movie") {
+        state.movie_recorder = Some(movie::MovieRecorder::create(
+            movie_path,
+            &rom_filename,
+            &rom_checksum,
+        )?);
+    } else if let Some(movie_path) = matches.value_of("play-movie") {
+        let movie_player = movie::MoviePlayer::from_file(movie_path)?;
+        // Movies without a checksum fall back to comparing the ROM file name.
+        let is_same_rom = if movie_player.rom_checksum().is_empty() {
+            movie_player.rom_filename() == rom_filename
+        } else {
+            movie_player.rom_checksum() == rom_checksum
+        };
+        if !is_same_rom {
+            warn!(
+                "Movie was recorded with a different ROM: {}.",
+                movie_player.rom_filename()
+            );
+        }
+        state.movie_player = Some(movie_player);
+    }
+    state.is_headless = matches.is_present("headless");
+    state.load_rom()?;
+    state.reset_sample_freq();
+    if state.uses_save_file() {
+        state.load()?;
+    }
+    state.load_save_state_slot()?;
+
+    let frames = match matches.value_of("frames") {
+        Some(frames) => Some(
+            frames
+                .parse::<u64>()
+                .map_err(|err| Error::new("parsing frames", &err))?,
+        ),
+        None => None,
     };
-    state
-        .nes
-        .load_rom(&fs::read(&state.rom_path).map_err(|err| Error::new("reading ROM", &err))?);
-    state.load()?;
+
+    if state.is_headless {
+        if let (Some(frames), Some((frame, _))) = (frames, &state.scheduled_screenshot) {
+            if frames < *frame {
+                return Err(Error::from_description(
+                    "running headless",
+                    "Expected `--frames` to be at least `--screenshot-at`.",
+                ));
+            }
+        }
+        return run_headless(&mut state, frames);
+    }
 
     let sdl_context =
         sdl2::init().map_err(|err| Error::from_description("initializing `sdl2`", err))?;
@@ -367,46 +1774,59 @@ fn run() -> Result<()> {
         Error::from_description("initializing `sdl2` game controller subsystem", err)
     })?;
 
-    let available = game_controller_subsystem
-        .num_joysticks()
-        .map_err(|err| Error::from_description("enumerating joysticks", err))?;
-    let mut _controller = None;
-    for id in 0..available {
-        if game_controller_subsystem.is_game_controller(id) {
-            match game_controller_subsystem.open(id) {
-                Ok(c) => {
-                    info!("[GUI] Opened controller: {}", c.name());
-                    _controller = Some(c);
-                    break;
-                }
-                Err(err) => error!("[GUI] Failed to open controller: {}", err),
-            }
-        }
+    let joystick_subsystem = sdl_context
+        .joystick()
+        .map_err(|err| Error::from_description("initializing `sdl2` joystick subsystem", err))?;
+
+    if let Some(controller_db) = &state.config.controller_db {
+        let mapping_count = game_controller_subsystem
+            .load_mappings(controller_db)
+            .map_err(|err| Error::new("loading controller database", &err))?;
+        info!("[GUI] Loaded {} controller mappings.", mapping_count);
     }
 
+    let mut controllers =
+        input::Controllers::open_all(&game_controller_subsystem, &joystick_subsystem)?;
+
     let (width, height) = state.window_dimensions();
     let window = video_subsystem
         .window("neso-gui", width, height)
         .position_centered()
+        .resizable()
         .opengl()
         .build()
         .map_err(|err| Error::new("building window", &err))?;
 
-    let mut canvas = window
-        .into_canvas()
+    let sync = state.config.audio_config.sync;
+    let mut canvas_builder = window.into_canvas();
+    if sync == config::SyncMode::Video {
+        canvas_builder = canvas_builder.present_vsync();
+    }
+    let mut canvas = canvas_builder
         .build()
         .map_err(|err| Error::new("building canvas", &err))?;
+    let is_vsync_enabled =
+        canvas.info().flags & sdl2::sys::SDL_RendererFlags::SDL_RENDERER_PRESENTVSYNC as u32 != 0;
+    if sync == config::SyncMode::Video && !is_vsync_enabled {
+        warn!("Vsync is not available, so frames are paced with a timer at the refresh rate.");
+    }
+    if state.debug_enabled {
+        canvas
+            .set_logical_size(width, height)
+            .map_err(|err| Error::new("setting canvas logical size", &err))?;
+    }
     let texture_creator = canvas.texture_creator();
+    let mut renderer = renderer::Renderer::new(&texture_creator, &state)?;
     canvas.present();
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
 
+    let audio_config = &state.config.audio_config;
     let audio_queue = audio_subsystem
         .open_queue::<f32, _>(
-            None,
+            audio_config.device.as_deref(),
             &AudioSpecDesired {
-                freq: Some(44_100),
+                freq: Some(audio_config.sample_rate as i32),
                 channels: Some(1),
-                samples: Some(1024),
+                samples: Some(audio_config.buffer_size),
             },
         )
         .map_err(|err| Error::from_description("opening audio queue", err))?;
@@ -416,12 +1836,9 @@ fn run() -> Result<()> {
         .event_pump()
         .map_err(|err| Error::from_description("obtaining `sdl` event pump", err))?;
 
-    if let Some(frames) = matches.value_of("frames") {
-        for _ in 0..frames
-            .parse()
-            .map_err(|err| Error::new("parsing frames", &err))?
-        {
-            state.nes.step_frame();
+    if let Some(frames) = frames {
+        for _ in 0..frames {
+            state.step_frame()?;
         }
     }
 
@@ -435,144 +1852,180 @@ fn run() -> Result<()> {
                 }
                 Event::KeyDown {
                     keycode: Some(keycode),
+                    keymod,
+                    repeat: false,
                     ..
                 } => {
-                    let keybinding_value = config::KeybindingValue::KeycodeValue(keycode);
-                    state.handle_button_press(keybinding_value)?;
+                    state.handle_key_press(keycode, keymod)?;
                 }
                 Event::KeyUp {
                     keycode: Some(keycode),
                     ..
                 } => {
-                    let keybinding_value = config::KeybindingValue::KeycodeValue(keycode);
-                    state.handle_button_release(keybinding_value);
+                    state.handle_key_release(keycode)?;
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Some(device) =
+                        controllers.open(&game_controller_subsystem, &joystick_subsystem, which)
+                    {
+                        state.handle_controller_added(&device);
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    if let Some(device) = controllers.close(which) {
+                        state.handle_controller_removed(&device);
+                    }
+                }
+                Event::ControllerAxisMotion {
+                    which, axis, value, ..
+                } => {
+                    if let Some(device) = controllers.get_device(which) {
+                        state.handle_axis_motion(
+                            input::AnalogAxis::Controller(axis),
+                            value,
+                            device,
+                        )?;
+                    }
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    state.handle_controller_button_press(button, controllers.get_device(which))?;
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    state
+                        .handle_controller_button_release(button, controllers.get_device(which))?;
+                }
+                Event::JoyDeviceAdded { which, .. } => {
+                    if let Some(device) = controllers.open_joystick(
+                        &game_controller_subsystem,
+                        &joystick_subsystem,
+                        which,
+                    ) {
+                        state.handle_controller_added(&device);
+                    }
+                }
+                Event::JoyDeviceRemoved { which, .. } => {
+                    if let Some(device) = controllers.close(which) {
+                        state.handle_controller_removed(&device);
+                    }
+                }
+                Event::JoyAxisMotion {
+                    which,
+                    axis_idx,
+                    value,
+                    ..
+                } => {
+                    if let Some(device) = controllers.get_joystick_device(which) {
+                        let axis = input::AnalogAxis::Joystick(axis_idx);
+                        state.handle_axis_motion(axis, value, device)?;
+                    }
+                }
+                Event::JoyHatMotion {
+                    which,
+                    hat_idx,
+                    state: hat_state,
+                    ..
+                } => {
+                    if let Some(device) = controllers.get_joystick_device(which) {
+                        state.handle_hat_motion(hat_idx, hat_state, device)?;
+                    }
                 }
-                Event::ControllerButtonDown { button, .. } => {
-                    let keybinding_value = config::KeybindingValue::ButtonValue(button);
-                    state.handle_button_press(keybinding_value)?;
+                Event::JoyButtonDown {
+                    which, button_idx, ..
+                } => {
+                    if let Some(device) = controllers.get_joystick_device(which) {
+                        let keybinding_value =
+                            config::KeybindingValue::JoystickButtonValue(button_idx);
+                        state.handle_button_press(keybinding_value, Some(device))?;
+                    }
                 }
-                Event::ControllerButtonUp { button, .. } => {
-                    let keybinding_value = config::KeybindingValue::ButtonValue(button);
-                    state.handle_button_release(keybinding_value);
+                Event::JoyButtonUp {
+                    which, button_idx, ..
+                } => {
+                    if let Some(device) = controllers.get_joystick_device(which) {
+                        let keybinding_value =
+                            config::KeybindingValue::JoystickButtonValue(button_idx);
+                        state.handle_button_release(keybinding_value, Some(device))?;
+                    }
                 }
                 _ => {}
             }
         }
 
-        if !state.is_paused {
-            state.nes.step_frame();
+        let refresh_rate = canvas
+            .window()
+            .display_mode()
+            .map(|display_mode| display_mode.refresh_rate)
+            .ok()
+            .filter(|refresh_rate| *refresh_rate > 0)
+            .unwrap_or(60);
+        let frames_to_step = state.get_frames_to_step(refresh_rate);
+        // Audio is muted while fast forwarding and advancing frames.
+        let is_queueing_audio = frames_to_step > 0
+            && !state.is_paused
+            && !state.is_muted
+            && !state.is_rewinding
+            && !state.is_fast_forwarding();
+        // Audio sync falls back to the timer while no audio is queued.
+        let is_audio_synced = is_queueing_audio && sync == config::SyncMode::Audio;
+        if is_audio_synced {
+            state.step_audio_synced_frames(&audio_queue)?;
+        } else {
+            for _ in 0..frames_to_step {
+                state.step_frame()?;
+                if is_queueing_audio {
+                    state.queue_audio(&audio_queue)?;
+                }
+            }
         }
-
-        if !state.is_paused && !state.is_muted {
-            let buffer_len = state.nes.audio_buffer_len();
-            let slice = unsafe { slice::from_raw_parts(state.nes.audio_buffer(), buffer_len) };
-            audio_queue.queue(&slice[0..buffer_len]);
+        if !state.is_paused && state.is_fast_forwarding() && state.config.fast_forward_speed == 0 {
+            while start.elapsed() < UNCAPPED_FAST_FORWARD_TIME {
+                state.step_uncapped_fast_forward_frame()?;
+            }
+        }
+        if is_queueing_audio && sync == config::SyncMode::Video {
+            state.adjust_sample_freq(&audio_queue);
         }
 
-        let mut texture = texture_creator
-            .create_texture_streaming(PixelFormatEnum::ABGR8888, 256, 240)
-            .map_err(|err| Error::new("creating output texture", &err))?;
-        texture
-            .with_lock(None, |buffer: &mut [u8], _pitch: usize| unsafe {
-                ptr::copy_nonoverlapping(
-                    state.nes.image_buffer(),
-                    buffer.as_mut_ptr(),
-                    240 * 256 * 4,
-                );
-            })
-            .map_err(|err| Error::from_description("locking output texture", err))?;
-        canvas
-            .copy(&texture, None, Some(Rect::new(0, 0, 256 * 2, 240 * 2)))
-            .map_err(|err| Error::from_description("copying output texture to canvas", err))?;
-
-        if state.debug_enabled {
-            let debug_data = graphics::DebugData::new(&state.nes);
-
-            let colors_rect = Rect::new(512, 480 + 16 * 4, 32 * 16, 32 * 4);
+        let fullscreen_type = state.fullscreen_type();
+        if canvas.window().fullscreen_state() != fullscreen_type {
             canvas
-                .copy(
-                    &graphics::get_colors_texture(&texture_creator, &debug_data)?,
-                    None,
-                    Some(colors_rect),
-                )
-                .map_err(|err| Error::from_description("copying colors texture to canvas", err))?;
-            canvas
-                .draw_rect(colors_rect)
-                .map_err(|err| Error::from_description("drawing colors border", err))?;
+                .window_mut()
+                .set_fullscreen(fullscreen_type)
+                .map_err(|err| Error::from_description("setting fullscreen", err))?;
+        }
 
-            let palettes_rect = Rect::new(512, 480 + 32 * 4 + 16 * 4, 32 * 16, 32 * 2);
-            canvas
-                .copy(
-                    &graphics::get_palettes_texture(&texture_creator, &debug_data)?,
-                    None,
-                    Some(palettes_rect),
-                )
-                .map_err(|err| {
-                    Error::from_description("copying palettes texture to canvas", err)
-                })?;
-            canvas
-                .draw_rect(palettes_rect)
-                .map_err(|err| Error::from_description("drawing palettes border", err))?;
+        renderer.render(&mut canvas, &state)?;
 
-            let oam_rect = Rect::new(512, 480, 16 * 32, 16 * 4);
-            canvas
-                .copy(
-                    &graphics::get_oam_texture(&texture_creator, &debug_data)?,
-                    None,
-                    Some(oam_rect),
-                )
-                .map_err(|err| Error::from_description("copying oam texture to canvas", err))?;
-            canvas
-                .draw_rect(oam_rect)
-                .map_err(|err| Error::from_description("drawing palettes border", err))?;
-
-            for bank_index in 0..4 {
-                canvas
-                    .copy(
-                        &graphics::get_nametable_texture(
-                            &texture_creator,
-                            &debug_data,
-                            bank_index,
-                        )?,
-                        None,
-                        Some(Rect::new(
-                            512 + 256 * (bank_index as i32 % 2),
-                            240 * (bank_index as i32 / 2),
-                            256,
-                            240,
-                        )),
-                    )
-                    .map_err(|err| {
-                        Error::from_description("copying nametable texture to canvas", err)
-                    })?;
-            }
-            canvas
-                .draw_rect(Rect::new(512, 0, 512, 480))
-                .map_err(|err| Error::from_description("drawing nametables border", err))?;
-
-            for table_index in 0..2 {
-                canvas
-                    .copy(
-                        &graphics::get_pattern_table_texture(
-                            &texture_creator,
-                            &debug_data,
-                            table_index,
-                        )?,
-                        None,
-                        Some(Rect::new(table_index as i32 * 256, 480, 256, 256)),
-                    )
-                    .map_err(|err| {
-                        Error::from_description("copying pattern table texture to canvas", err)
-                    })?;
-            }
+        if let Some(debug_screenshot_file_path) = state.pending_debug_screenshot.take() {
+            let (width, height) = canvas
+                .output_size()
+                .map_err(|err| Error::from_description("getting canvas size", err))?;
+            let pixels = canvas
+                .read_pixels(None, PixelFormatEnum::ABGR8888)
+                .map_err(|err| Error::from_description("reading canvas pixels", err))?;
+            screenshot::write_png(debug_screenshot_file_path, width, height, &pixels)?;
         }
 
         canvas.present();
 
         let elapsed = start.elapsed();
-        let mus_per_frame = state.mus_per_frame();
-        if mus_per_frame > elapsed {
-            thread::sleep(mus_per_frame - elapsed);
+        renderer.record_frame_time(elapsed);
+        if is_audio_synced {
+            state.wait_for_audio_queue(&audio_queue);
+            continue;
+        }
+
+        // With video sync, presenting the canvas already waits for the next vertical blank, unless
+        // the driver ignores vsync and presents right away.
+        let frame_duration = if sync == config::SyncMode::Video {
+            Duration::from_micros((1e6 / f64::from(refresh_rate)).round() as u64)
+        } else {
+            state.mus_per_frame()
+        };
+        let is_vsync_waiting =
+            sync == config::SyncMode::Video && is_vsync_enabled && elapsed >= frame_duration / 2;
+        if !is_vsync_waiting && frame_duration > elapsed {
+            thread::sleep(frame_duration - elapsed);
         }
     }
     Ok(())
@@ -0,0 +1,365 @@
+use super::{Error, Result};
+use log::info;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// FM2 lists buttons from the most significant bit to the least significant bit of the controller
+// state, which matches the button indices used by `neso`.
+const FM2_BUTTONS: [char; 8] = ['R', 'L', 'D', 'U', 'T', 'S', 'B', 'A'];
+
+// FCEUX checksums the PRG and CHR ROM of an iNES file without its header and trainer.
+pub fn rom_checksum(rom: &[u8]) -> String {
+    let mut data = rom;
+    if rom.len() >= 16 && rom[..4] == *b"NES\x1a" {
+        let trainer_size = if rom[6] & 0x04 != 0 { 512 } else { 0 };
+        let start = (16 + trainer_size).min(rom.len());
+        let end = start + usize::from(rom[4]) * 0x4000 + usize::from(rom[5]) * 0x2000;
+        data = &rom[start..end.min(rom.len())];
+    }
+    format!("base64:{}", base64::encode(md5::compute(data).0))
+}
+
+// FCEUX only uses the GUID to tell movies apart, so it is derived from the ROM checksum and the
+// current time instead of being random.
+fn generate_guid(rom_checksum: &str) -> String {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    let digest = md5::compute(format!("{}{}", rom_checksum, time));
+    let hex = digest
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+pub const SOFT_RESET: u8 = 0x01;
+pub const HARD_RESET: u8 = 0x02;
+
+#[derive(Clone, Copy, Default)]
+pub struct MovieFrame {
+    pub commands: u8,
+    pub buttons: [u8; 2],
+}
+
+pub struct Movie {
+    pub rom_filename: String,
+    pub rom_checksum: String,
+    pub frames: Vec<MovieFrame>,
+}
+
+fn parse_buttons(field: &str, line_number: usize) -> Result<u8> {
+    if field.is_empty() {
+        return Ok(0);
+    }
+
+    if field.chars().count() != FM2_BUTTONS.len() {
+        return Err(Error::from_description(
+            "parsing movie",
+            format!(
+                "Expected 8 buttons in controller field on line {}.",
+                line_number
+            ),
+        ));
+    }
+
+    Ok(field
+        .chars()
+        .enumerate()
+        .filter(|(_, button)| *button != '.' && *button != ' ')
+        .fold(0, |buttons, (index, _)| buttons | 1 << (7 - index)))
+}
+
+fn format_buttons(buttons: u8) -> String {
+    FM2_BUTTONS
+        .iter()
+        .enumerate()
+        .map(|(index, button)| {
+            if buttons & 1 << (7 - index) != 0 {
+                *button
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+impl Movie {
+    pub fn new<T>(rom_filename: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Movie {
+            rom_filename: rom_filename.into(),
+            rom_checksum: String::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn from_fm2(data: &str) -> Result<Movie> {
+        let mut movie = Movie::new("");
+        let mut ports = [1, 1];
+
+        for (line_index, line) in data.lines().enumerate() {
+            let line_number = line_index + 1;
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('|') {
+                let fields: Vec<&str> = line.split('|').collect();
+                if fields.len() < 4 {
+                    return Err(Error::from_description(
+                        "parsing movie",
+                        format!("Expected input fields on line {}.", line_number),
+                    ));
+                }
+                let commands = fields[1].trim().parse::<u8>().map_err(|_| {
+                    Error::from_description(
+                        "parsing movie",
+                        format!(
+                            "Expected commands to be an integer on line {}.",
+                            line_number
+                        ),
+                    )
+                })?;
+                let mut frame = MovieFrame {
+                    commands,
+                    buttons: [0, 0],
+                };
+                for port in 0..2 {
+                    if ports[port] == 1 {
+                        frame.buttons[port] = parse_buttons(fields[port + 2], line_number)?;
+                    }
+                }
+                movie.frames.push(frame);
+                continue;
+            }
+
+            let mut header = line.splitn(2, ' ');
+            let key = header.next().unwrap_or("");
+            let value = header.next().unwrap_or("").trim();
+            match key {
+                "romFilename" => movie.rom_filename = value.to_owned(),
+                "romChecksum" => movie.rom_checksum = value.to_owned(),
+                "fourscore" if value != "0" => {
+                    return Err(Error::from_description(
+                        "parsing movie",
+                        "Four Score movies are not supported.",
+                    ));
+                }
+                "port0" | "port1" => {
+                    let port = if key == "port0" { 0 } else { 1 };
+                    ports[port] = match value {
+                        "0" => 0,
+                        "1" => 1,
+                        _ => {
+                            return Err(Error::from_description(
+                                "parsing movie",
+                                format!("Unsupported device in `{}`: {}.", key, value),
+                            ));
+                        }
+                    };
+                }
+                _ => {}
+            }
+        }
+
+        Ok(movie)
+    }
+}
+
+fn format_fm2_header(rom_filename: &str, rom_checksum: &str) -> String {
+    let mut data = String::new();
+    data.push_str("version 3\n");
+    data.push_str(&format!("emuVersion {}\n", env!("CARGO_PKG_VERSION")));
+    data.push_str("rerecordCount 0\n");
+    data.push_str("palFlag 0\n");
+    data.push_str(&format!("romFilename {}\n", rom_filename));
+    data.push_str(&format!("romChecksum {}\n", rom_checksum));
+    data.push_str(&format!("guid {}\n", generate_guid(rom_checksum)));
+    data.push_str("fourscore 0\n");
+    data.push_str("microphone 0\n");
+    data.push_str("port0 1\n");
+    data.push_str("port1 1\n");
+    data.push_str("port2 0\n");
+    data.push_str("FDS 0\n");
+    data.push_str("NewPPU 0\n");
+    data
+}
+
+fn format_fm2_frame(frame: &MovieFrame) -> String {
+    format!(
+        "|{}|{}|{}||\n",
+        frame.commands,
+        format_buttons(frame.buttons[0]),
+        format_buttons(frame.buttons[1]),
+    )
+}
+
+// Frames are written as they are recorded, so the movie is kept if the emulator exits with an
+// error.
+pub struct MovieRecorder {
+    writer: BufWriter<File>,
+    next_frame: MovieFrame,
+}
+
+impl MovieRecorder {
+    pub fn create<P>(movie_path: P, rom_filename: &str, rom_checksum: &str) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        info!("[GUI] Writing movie file at {:?}.", movie_path.as_ref());
+        let file =
+            File::create(movie_path).map_err(|err| Error::new("creating movie file", &err))?;
+        let mut movie_recorder = MovieRecorder {
+            writer: BufWriter::new(file),
+            next_frame: MovieFrame::default(),
+        };
+        movie_recorder.write(&format_fm2_header(rom_filename, rom_checksum))?;
+        Ok(movie_recorder)
+    }
+
+    fn write(&mut self, data: &str) -> Result<()> {
+        self.writer
+            .write_all(data.as_bytes())
+            .map_err(|err| Error::new("writing movie", &err))
+    }
+
+    pub fn press_button(&mut self, port: usize, button_index: u8) {
+        self.next_frame.buttons[port] |= 1 << button_index;
+    }
+
+    pub fn release_button(&mut self, port: usize, button_index: u8) {
+        self.next_frame.buttons[port] &= !(1 << button_index);
+    }
+
+    pub fn reset(&mut self) {
+        self.next_frame.commands |= SOFT_RESET;
+    }
+
+    pub fn record_frame(&mut self) -> Result<()> {
+        let frame = format_fm2_frame(&self.next_frame);
+        self.next_frame.commands = 0;
+        self.write(&frame)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer
+            .flush()
+            .map_err(|err| Error::new("writing movie", &err))
+    }
+}
+
+pub struct MoviePlayer {
+    movie: Movie,
+    frame_index: usize,
+}
+
+impl MoviePlayer {
+    pub fn from_file<P>(movie_path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        info!("[GUI] Reading movie file at {:?}.", movie_path.as_ref());
+        let data =
+            fs::read_to_string(movie_path).map_err(|err| Error::new("reading movie", &err))?;
+        Ok(MoviePlayer {
+            movie: Movie::from_fm2(&data)?,
+            frame_index: 0,
+        })
+    }
+
+    pub fn rom_filename(&self) -> &str {
+        &self.movie.rom_filename
+    }
+
+    pub fn rom_checksum(&self) -> &str {
+        &self.movie.rom_checksum
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame_index >= self.movie.frames.len()
+    }
+
+    pub fn next_frame(&mut self) -> Option<MovieFrame> {
+        let frame = self.movie.frames.get(self.frame_index).cloned();
+        self.frame_index += 1;
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_checksum_skips_header_and_trainer() {
+        let prg = (0..0x4000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let chr = (0..0x2000)
+            .map(|i| (i * 7 % 256) as u8)
+            .collect::<Vec<u8>>();
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0x04, 0];
+        rom.resize(16, 0);
+        rom.extend(vec![0xFF; 512]);
+        rom.extend(&prg);
+        rom.extend(&chr);
+        rom.extend(b"trailing data");
+
+        assert_eq!(rom_checksum(&rom), "base64:UEtL4r4LV6oFwk2uNXh4AA==");
+        assert_eq!(rom_checksum(b""), "base64:1B2M2Y8AsgTpgAmY7PhCfg==");
+        assert_eq!(rom_checksum(b"abc"), "base64:kAFQmDzST7DWlj99KOF/cg==");
+    }
+
+    #[test]
+    fn fm2_round_trip() {
+        let frames = [
+            MovieFrame {
+                commands: HARD_RESET,
+                buttons: [0, 0],
+            },
+            MovieFrame {
+                commands: 0,
+                buttons: [0x81, 0x00],
+            },
+            MovieFrame {
+                commands: SOFT_RESET,
+                buttons: [0xFF, 0x5A],
+            },
+        ];
+        let mut data = format_fm2_header("game.nes", "base64:kAFQmDzST7DWlj99KOF/cg==");
+        for frame in &frames {
+            data.push_str(&format_fm2_frame(frame));
+        }
+
+        let movie = Movie::from_fm2(&data).expect("Expected movie to parse.");
+        assert_eq!(movie.rom_filename, "game.nes");
+        assert_eq!(movie.rom_checksum, "base64:kAFQmDzST7DWlj99KOF/cg==");
+        assert_eq!(movie.frames.len(), frames.len());
+        for (parsed_frame, frame) in movie.frames.iter().zip(&frames) {
+            assert_eq!(parsed_frame.commands, frame.commands);
+            assert_eq!(parsed_frame.buttons, frame.buttons);
+        }
+        assert_eq!(format_fm2_frame(&frames[1]), "|0|R......A|........||\n");
+    }
+
+    #[test]
+    fn fm2_parses_empty_and_spaced_buttons() {
+        let movie =
+            Movie::from_fm2("port1 0\n|0|R D    A|||\n|1|||\n").expect("Expected movie to parse.");
+        assert_eq!(movie.frames[0].buttons, [0xA1, 0]);
+        assert_eq!(movie.frames[1].commands, SOFT_RESET);
+        assert_eq!(movie.frames[1].buttons, [0, 0]);
+    }
+}
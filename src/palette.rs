@@ -0,0 +1,107 @@
+use super::{Error, Result};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const PALETTE_SIZE: usize = 64;
+const EMPHASIS_PALETTE_SIZE: usize = PALETTE_SIZE * 8;
+// Index of the black that games usually use, which is preferred over the other blacks since the
+// default palette does not distinguish between them.
+const BLACK_INDEX: usize = 0x0F;
+
+pub struct Palette {
+    colors: Vec<u32>,
+    color_indices: HashMap<u32, usize>,
+}
+
+impl Palette {
+    pub fn from_file<P>(path: P, default_colors: &[u32]) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        info!("[GUI] Reading palette file at {:?}.", path.as_ref());
+        let data = fs::read(path).map_err(|err| Error::new("reading palette", &err))?;
+        match data.len() {
+            len if len == PALETTE_SIZE * 3 => {}
+            len if len == EMPHASIS_PALETTE_SIZE * 3 => {
+                warn!(
+                    "Only the first 64 colors of the palette are used since `neso` has no color \
+                     emphasis."
+                );
+            }
+            _ => {
+                return Err(Error::from_description(
+                    "parsing palette",
+                    "Expected palette to be 192 or 1536 bytes.",
+                ));
+            }
+        }
+
+        let colors = data
+            .chunks(3)
+            .take(PALETTE_SIZE)
+            .map(|color| u32::from(color[0]) << 16 | u32::from(color[1]) << 8 | u32::from(color[2]))
+            .collect::<Vec<u32>>();
+
+        // `neso` outputs RGB values instead of palette indices, so map them back to their index in
+        // the default palette. Indices that share a color in the default palette cannot be told
+        // apart, so all of them are drawn with the color of one index.
+        let mut color_indices = HashMap::new();
+        color_indices.insert(default_colors[BLACK_INDEX], BLACK_INDEX);
+        for (index, color) in default_colors.iter().enumerate() {
+            color_indices.entry(*color).or_insert(index);
+        }
+        for drawn_index in color_indices.values() {
+            let differing_indices = default_colors
+                .iter()
+                .enumerate()
+                .filter(|(index, color)| {
+                    color_indices[color] == *drawn_index && colors[*index] != colors[*drawn_index]
+                })
+                .map(|(index, _)| format!("${:02X}", index))
+                .collect::<Vec<String>>();
+            if !differing_indices.is_empty() {
+                warn!(
+                    "Palette colors {} are drawn as ${:02X} since `neso` outputs the same color for \
+                     them.",
+                    differing_indices.join(", "),
+                    drawn_index,
+                );
+            }
+        }
+
+        Ok(Palette {
+            colors,
+            color_indices,
+        })
+    }
+
+    pub fn colors(&self) -> &[u32] {
+        &self.colors
+    }
+
+    // Neighboring pixels usually have the same color, so the last mapped color is reused to avoid
+    // looking up every pixel.
+    pub fn apply(&self, image_buffer: &[u8], output_buffer: &mut [u8]) {
+        let mut last_color = None;
+        for (pixel, output_pixel) in image_buffer.chunks(4).zip(output_buffer.chunks_mut(4)) {
+            let color = u32::from(pixel[0]) << 16 | u32::from(pixel[1]) << 8 | u32::from(pixel[2]);
+            let color = match last_color {
+                Some((input_color, output_color)) if input_color == color => output_color,
+                _ => {
+                    let output_color = match self.color_indices.get(&color) {
+                        Some(index) => self.colors[*index],
+                        None => color,
+                    };
+                    last_color = Some((color, output_color));
+                    output_color
+                }
+            };
+            output_pixel[0] = ((color >> 16) & 0xFF) as u8;
+            output_pixel[1] = ((color >> 8) & 0xFF) as u8;
+            output_pixel[2] = (color & 0xFF) as u8;
+            output_pixel[3] = pixel[3];
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use super::{Error, Result};
+use log::info;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// The NTSC NES runs at 39375000 / 655171 (about 60.0988) frames per second.
+const FRAME_RATE_NUMERATOR: u32 = 39_375_000;
+const FRAME_RATE_DENOMINATOR: u32 = 655_171;
+const WAV_HEADER_SIZE: u32 = 58;
+// The sizes in the WAV header are 32-bit, so a file holds at most about 4 GiB of samples.
+const MAX_WAV_SAMPLE_COUNT: u32 = (u32::MAX - (WAV_HEADER_SIZE - 8)) / 4;
+
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    sample_count: u32,
+}
+
+impl WavWriter {
+    pub fn create<P>(path: P, sample_rate: u32) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        info!("[GUI] Writing audio at {:?}.", path.as_ref());
+        let file = File::create(path).map_err(|err| Error::new("creating audio file", &err))?;
+        let mut wav_writer = WavWriter {
+            writer: BufWriter::new(file),
+            sample_count: 0,
+        };
+        wav_writer.write_header(sample_rate)?;
+        Ok(wav_writer)
+    }
+
+    fn write_header(&mut self, sample_rate: u32) -> Result<()> {
+        let mut header = Vec::with_capacity(WAV_HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(WAV_HEADER_SIZE - 8).to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        // 32-bit IEEE float mono samples.
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&18u32.to_le_bytes());
+        header.extend_from_slice(&3u16.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * 4).to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&32u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(b"fact");
+        header.extend_from_slice(&4u32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        self.writer
+            .write_all(&header)
+            .map_err(|err| Error::new("writing audio header", &err))
+    }
+
+    // Samples past the size limit of the file are dropped, so callers should stop writing once the
+    // writer is full.
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        let remaining_count = (MAX_WAV_SAMPLE_COUNT - self.sample_count) as usize;
+        let samples = &samples[..samples.len().min(remaining_count)];
+        for sample in samples {
+            self.writer
+                .write_all(&sample.to_bits().to_le_bytes())
+                .map_err(|err| Error::new("writing audio samples", &err))?;
+        }
+        self.sample_count += samples.len() as u32;
+        Ok(())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.sample_count == MAX_WAV_SAMPLE_COUNT
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        let data_size = self.sample_count * 4;
+        let patches = [
+            (4, WAV_HEADER_SIZE - 8 + data_size),
+            (46, self.sample_count),
+            (54, data_size),
+        ];
+        for (offset, value) in &patches {
+            self.writer
+                .seek(SeekFrom::Start(*offset))
+                .and_then(|_| self.writer.write_all(&value.to_le_bytes()))
+                .map_err(|err| Error::new("finishing audio file", &err))?;
+        }
+        self.writer
+            .flush()
+            .map_err(|err| Error::new("finishing audio file", &err))
+    }
+}
+
+pub struct Y4mWriter {
+    writer: BufWriter<File>,
+    width: usize,
+    height: usize,
+}
+
+impl Y4mWriter {
+    pub fn create<P>(path: P, width: usize, height: usize) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        info!("[GUI] Writing video at {:?}.", path.as_ref());
+        let file = File::create(path).map_err(|err| Error::new("creating video file", &err))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+            width, height, FRAME_RATE_NUMERATOR, FRAME_RATE_DENOMINATOR,
+        )
+        .map_err(|err| Error::new("writing video header", &err))?;
+        Ok(Y4mWriter {
+            writer,
+            width,
+            height,
+        })
+    }
+
+    // Converts RGBA pixels to full resolution BT.601 YCbCr planes.
+    pub fn write_frame(&mut self, rgba: &[u8]) -> Result<()> {
+        let pixel_count = self.width * self.height;
+        let mut planes = vec![0; pixel_count * 3];
+        for (index, pixel) in rgba.chunks(4).take(pixel_count).enumerate() {
+            let r = i32::from(pixel[0]);
+            let g = i32::from(pixel[1]);
+            let b = i32::from(pixel[2]);
+            planes[index] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+            planes[pixel_count + index] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            planes[pixel_count * 2 + index] =
+                (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+        self.writer
+            .write_all(b"FRAME\n")
+            .and_then(|_| self.writer.write_all(&planes))
+            .map_err(|err| Error::new("writing video frame", &err))
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.writer
+            .flush()
+            .map_err(|err| Error::new("finishing video file", &err))
+    }
+}
+
+pub struct AvRecorder {
+    video_writer: Y4mWriter,
+    audio_writer: WavWriter,
+    resample_position: f64,
+}
+
+impl AvRecorder {
+    pub fn create<P>(path: P, width: usize, height: usize, sample_rate: u32) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(AvRecorder {
+            video_writer: Y4mWriter::create(path.as_ref().with_extension("y4m"), width, height)?,
+            audio_writer: WavWriter::create(path.as_ref().with_extension("wav"), sample_rate)?,
+            resample_position: 0.0,
+        })
+    }
+
+    // `speed` is the ratio between the recording sample rate and the rate that the samples were
+    // generated at, so the audio is resampled to stay in sync with the video at any speed.
+    pub fn record_frame(&mut self, image_buffer: &[u8], samples: &[f32], speed: f32) -> Result<()> {
+        self.video_writer.write_frame(image_buffer)?;
+
+        let step = 1.0 / f64::from(speed);
+        let mut resampled_samples = Vec::with_capacity(samples.len());
+        while self.resample_position < samples.len() as f64 {
+            let index = self.resample_position as usize;
+            let t = (self.resample_position - index as f64) as f32;
+            let curr_sample = samples[index];
+            let next_sample = samples.get(index + 1).cloned().unwrap_or(curr_sample);
+            resampled_samples.push(curr_sample * (1.0 - t) + next_sample * t);
+            self.resample_position += step;
+        }
+        self.resample_position -= samples.len() as f64;
+        self.audio_writer.write_samples(&resampled_samples)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.audio_writer.is_full()
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.video_writer.finish()?;
+        self.audio_writer.finish()
+    }
+}
@@ -0,0 +1,163 @@
+use super::{config, movie, rewind, EmulatorState, Error, Result};
+use log::{error, info};
+use serde_derive::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+struct FnvHasher(u64);
+
+impl FnvHasher {
+    fn new() -> Self {
+        FnvHasher(FNV_OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(rename = "test", default)]
+    tests: Vec<TestCase>,
+}
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: Option<String>,
+    rom: PathBuf,
+    frames: u64,
+    movie: Option<PathBuf>,
+    video_hash: Option<String>,
+    audio_hash: Option<String>,
+}
+
+struct TestResult {
+    video_hash: String,
+    audio_hash: String,
+}
+
+fn run_test<P>(test_case: &TestCase, base_path: &Path, config_path: P) -> Result<TestResult>
+where
+    P: AsRef<Path>,
+{
+    let mut config = config::Config::parse_config(config_path)?;
+    // The hashes do not depend on the audio device, so always generate audio at 44.1 kHz.
+    config.audio_config.sample_rate = 44_100;
+    let mut state = EmulatorState::new(config, base_path.join(&test_case.rom).to_string_lossy());
+    // Rewinding is never used while testing, so avoid the cost of taking snapshots.
+    state.rewind_buffer = rewind::RewindBuffer::new(0, 1);
+    if let Some(movie_path) = &test_case.movie {
+        state.movie_player = Some(movie::MoviePlayer::from_file(base_path.join(movie_path))?);
+    }
+    state.load_rom()?;
+    state.reset_sample_freq();
+
+    let mut audio_hasher = FnvHasher::new();
+    for _ in 0..test_case.frames {
+        state.step_frame()?;
+        for sample in state.audio_buffer() {
+            audio_hasher.write(&sample.to_bits().to_le_bytes());
+        }
+    }
+
+    let mut video_hasher = FnvHasher::new();
+    video_hasher.write(state.image_buffer());
+
+    Ok(TestResult {
+        video_hash: video_hasher.finish(),
+        audio_hash: audio_hasher.finish(),
+    })
+}
+
+fn check_hash(name: &str, kind: &str, expected: &Option<String>, actual: &str) -> bool {
+    match expected {
+        Some(expected) if expected.eq_ignore_ascii_case(actual) => true,
+        Some(expected) => {
+            error!(
+                "[TEST] {}: expected {} hash {}, got {}.",
+                name, kind, expected, actual
+            );
+            false
+        }
+        None => {
+            error!(
+                "[TEST] {}: missing expected {} hash, got {}.",
+                name, kind, actual
+            );
+            false
+        }
+    }
+}
+
+pub fn run_tests<P, Q>(manifest_path: P, config_path: Q) -> Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let manifest_data = fs::read_to_string(&manifest_path)
+        .map_err(|err| Error::new("reading test manifest", &err))?;
+    let manifest = toml::from_str::<Manifest>(&manifest_data)
+        .map_err(|err| Error::new("parsing test manifest", &err))?;
+    let base_path = manifest_path
+        .as_ref()
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+
+    let mut failed_tests = 0;
+    for test_case in &manifest.tests {
+        let name = match &test_case.name {
+            Some(name) => name.clone(),
+            None => test_case.rom.to_string_lossy().into_owned(),
+        };
+        let is_passing = match run_test(test_case, base_path, &config_path) {
+            Ok(test_result) => {
+                let is_video_passing = check_hash(
+                    &name,
+                    "video",
+                    &test_case.video_hash,
+                    &test_result.video_hash,
+                );
+                let is_audio_passing = check_hash(
+                    &name,
+                    "audio",
+                    &test_case.audio_hash,
+                    &test_result.audio_hash,
+                );
+                is_video_passing && is_audio_passing
+            }
+            Err(err) => {
+                error!("[TEST] {}: {}", name, err);
+                false
+            }
+        };
+        if is_passing {
+            info!("[TEST] {}: ok.", name);
+        } else {
+            failed_tests += 1;
+        }
+    }
+
+    info!(
+        "[TEST] {} passed, {} failed.",
+        manifest.tests.len() - failed_tests,
+        failed_tests
+    );
+    if failed_tests > 0 {
+        return Err(Error::from_description(
+            "running tests",
+            format!("{} of {} tests failed.", failed_tests, manifest.tests.len()),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use super::config::{self, Config, ControllerConfig, KeybindingValue, KeybindingsConfig};
+use super::input::InputDevice;
+use log::info;
+use std::time::{Duration, Instant};
+
+// Time that the `remap` keybinding has to be held to cancel remapping instead of skipping.
+const CANCEL_HOLD_DURATION: Duration = Duration::from_secs(1);
+
+enum RemapTarget {
+    Port(usize, usize),
+    Action(String),
+}
+
+impl RemapTarget {
+    fn description(&self) -> String {
+        match self {
+            RemapTarget::Port(port, field_index) => format!(
+                "`{}` of `port-{}`",
+                config::CONTROLLER_FIELDS[*field_index],
+                port + 1
+            ),
+            RemapTarget::Action(name) => format!("`{}`", name),
+        }
+    }
+}
+
+// Walks through the fields of each console port and then every action, binding each one to the
+// next input. Pressing the `remap` keybinding skips the current field or action and keeps its
+// bindings, and holding it cancels remapping and restores the bindings from before the session.
+// `remap` itself is not a target since pressing it always skips.
+pub struct RemapSession {
+    targets: Vec<RemapTarget>,
+    target_index: usize,
+    // Key repeats of the input that started the session are ignored until it is released.
+    ignored_value: Option<KeybindingValue>,
+    // An input is bound once it is released, so that modifiers or the first button of a chord
+    // can be held without binding them on their own.
+    pending_value: Option<(KeybindingValue, Option<InputDevice>, Instant)>,
+    is_changed: bool,
+    is_canceled: bool,
+    was_paused: bool,
+    original_keybindings_config: KeybindingsConfig,
+    original_controller_configs: [ControllerConfig; config::PORT_COUNT],
+}
+
+impl RemapSession {
+    pub fn new(config: &Config, keybinding_value: KeybindingValue, was_paused: bool) -> Self {
+        let targets = (0..config::PORT_COUNT)
+            .flat_map(|port| {
+                (0..config::CONTROLLER_FIELDS.len())
+                    .map(move |field_index| RemapTarget::Port(port, field_index))
+            })
+            .chain(
+                KeybindingsConfig::action_names()
+                    .into_iter()
+                    .filter(|name| name != "remap")
+                    .map(RemapTarget::Action),
+            )
+            .collect();
+        let remap_session = RemapSession {
+            targets,
+            target_index: 0,
+            ignored_value: Some(keybinding_value),
+            pending_value: None,
+            is_changed: false,
+            is_canceled: false,
+            was_paused,
+            original_keybindings_config: config.keybindings_config.clone(),
+            original_controller_configs: config.controller_configs.clone(),
+        };
+        remap_session.log_prompt();
+        remap_session
+    }
+
+    fn log_prompt(&self) {
+        if let Some(target) = self.targets.get(self.target_index) {
+            info!(
+                "[GUI] Press an input for {}, `remap` to skip it, or hold `remap` to cancel.",
+                target.description()
+            );
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.is_canceled || self.target_index >= self.targets.len()
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.is_changed
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.is_canceled
+    }
+
+    pub fn restore_bindings(&self, config: &mut Config) {
+        config.keybindings_config = self.original_keybindings_config.clone();
+        config.controller_configs = self.original_controller_configs.clone();
+    }
+
+    pub fn was_paused(&self) -> bool {
+        self.was_paused
+    }
+
+    fn next_target(&mut self) {
+        self.target_index += 1;
+        self.log_prompt();
+    }
+
+    pub fn handle_button_press(
+        &mut self,
+        keybinding_value: KeybindingValue,
+        device: Option<&InputDevice>,
+    ) {
+        if self.is_finished() || self.ignored_value == Some(keybinding_value) {
+            return;
+        }
+        // Key repeats keep the time of the first press so that holding `remap` can be measured.
+        match &self.pending_value {
+            Some((pending_value, _, _)) if *pending_value == keybinding_value => {}
+            _ => self.pending_value = Some((keybinding_value, device.cloned(), Instant::now())),
+        }
+    }
+
+    // The input only replaces the bindings of the same type, so binding a button keeps the keyboard
+    // bindings and vice versa. Inputs from a device that does not drive the port are ignored.
+    pub fn handle_button_release(
+        &mut self,
+        config: &mut Config,
+        keybinding_value: KeybindingValue,
+    ) {
+        if self.ignored_value == Some(keybinding_value) {
+            self.ignored_value = None;
+            return;
+        }
+        let (keybinding_value, device, pressed_at) = match self.pending_value.take() {
+            Some(pending_value) => pending_value,
+            None => return,
+        };
+        if self.is_finished() {
+            return;
+        }
+
+        if config.keybindings_config.remap.contains(&keybinding_value) {
+            if pressed_at.elapsed() >= CANCEL_HOLD_DURATION {
+                self.is_canceled = true;
+            } else {
+                self.next_target();
+            }
+            return;
+        }
+
+        let target = &self.targets[self.target_index];
+        match target {
+            RemapTarget::Port(port, field_index) => {
+                let controller_config = &mut config.controller_configs[*port];
+                if !controller_config.matches_device(device.as_ref()) {
+                    return;
+                }
+                controller_config.set_keybinding(*field_index, keybinding_value);
+            }
+            RemapTarget::Action(name) => config
+                .keybindings_config
+                .set_keybinding(name, keybinding_value),
+        }
+
+        info!(
+            "[GUI] Bound {} to {}.",
+            target.description(),
+            keybinding_value.name()
+        );
+        self.is_changed = true;
+        self.next_target();
+    }
+}
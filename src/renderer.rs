@@ -0,0 +1,255 @@
+use super::{graphics, EmulatorState, Error, Result};
+use log::info;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use std::time::Duration;
+
+// Number of frames to average over before logging the frame time in debug mode.
+const FRAME_TIME_SAMPLES: u32 = 300;
+
+fn create_texture<'a>(
+    texture_creator: &'a TextureCreator<WindowContext>,
+    pixel_format: PixelFormatEnum,
+    (width, height): (u32, u32),
+    name: &str,
+) -> Result<Texture<'a>> {
+    texture_creator
+        .create_texture_streaming(pixel_format, width, height)
+        .map_err(|err| Error::new(format!("creating {} texture", name), &err))
+}
+
+struct DebugTextures<'a> {
+    colors: Texture<'a>,
+    palettes: Texture<'a>,
+    oam: Texture<'a>,
+    nametables: Vec<Texture<'a>>,
+    pattern_tables: Vec<Texture<'a>>,
+}
+
+impl<'a> DebugTextures<'a> {
+    fn new(texture_creator: &'a TextureCreator<WindowContext>) -> Result<Self> {
+        let rgb24 = PixelFormatEnum::RGB24;
+        Ok(DebugTextures {
+            colors: create_texture(
+                texture_creator,
+                rgb24,
+                graphics::COLORS_TEXTURE_SIZE,
+                "colors",
+            )?,
+            palettes: create_texture(
+                texture_creator,
+                rgb24,
+                graphics::PALETTES_TEXTURE_SIZE,
+                "palettes",
+            )?,
+            oam: create_texture(texture_creator, rgb24, graphics::OAM_TEXTURE_SIZE, "oam")?,
+            nametables: (0..4)
+                .map(|_| {
+                    create_texture(
+                        texture_creator,
+                        rgb24,
+                        graphics::NAMETABLE_TEXTURE_SIZE,
+                        "nametable",
+                    )
+                })
+                .collect::<Result<_>>()?,
+            pattern_tables: (0..2)
+                .map(|_| {
+                    create_texture(
+                        texture_creator,
+                        rgb24,
+                        graphics::PATTERN_TABLE_TEXTURE_SIZE,
+                        "pattern table",
+                    )
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+// Owns the textures drawn every frame so that they are only updated instead of recreated.
+pub struct Renderer<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>,
+    main_texture: Texture<'a>,
+    main_texture_size: (u32, u32),
+    debug_textures: Option<DebugTextures<'a>>,
+    frame_time_total: Duration,
+    frame_time_count: u32,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(
+        texture_creator: &'a TextureCreator<WindowContext>,
+        state: &EmulatorState,
+    ) -> Result<Self> {
+        let main_texture_size = Renderer::get_main_texture_size(state);
+        let debug_textures = if state.debug_enabled {
+            Some(DebugTextures::new(texture_creator)?)
+        } else {
+            None
+        };
+        Ok(Renderer {
+            texture_creator,
+            main_texture: create_texture(
+                texture_creator,
+                PixelFormatEnum::ABGR8888,
+                main_texture_size,
+                "output",
+            )?,
+            main_texture_size,
+            debug_textures,
+            frame_time_total: Duration::default(),
+            frame_time_count: 0,
+        })
+    }
+
+    fn get_main_texture_size(state: &EmulatorState) -> (u32, u32) {
+        let (scale_x, scale_y) = state.filter.scale();
+        (256 * scale_x, 240 * scale_y)
+    }
+
+    fn update_main_texture(&mut self, state: &EmulatorState) -> Result<()> {
+        // The texture size depends on the filter, which can change while running.
+        let main_texture_size = Renderer::get_main_texture_size(state);
+        if main_texture_size != self.main_texture_size {
+            self.main_texture = create_texture(
+                self.texture_creator,
+                PixelFormatEnum::ABGR8888,
+                main_texture_size,
+                "output",
+            )?;
+            self.main_texture_size = main_texture_size;
+        }
+
+        self.main_texture
+            .with_lock(None, |buffer: &mut [u8], _pitch: usize| {
+                state
+                    .filter
+                    .apply(&state.get_palette_image(), 256, 240, buffer, state.frame);
+            })
+            .map_err(|err| Error::from_description("locking output texture", err))
+    }
+
+    pub fn render(&mut self, canvas: &mut Canvas<Window>, state: &EmulatorState) -> Result<()> {
+        self.update_main_texture(state)?;
+
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+
+        let main_view_rect = if state.debug_enabled {
+            state.get_main_view_rect(512, 480)
+        } else {
+            let (output_width, output_height) = canvas
+                .output_size()
+                .map_err(|err| Error::from_description("getting canvas size", err))?;
+            state.get_main_view_rect(output_width, output_height)
+        };
+        canvas
+            .copy(
+                &self.main_texture,
+                Some(state.main_view_source_rect()),
+                Some(main_view_rect),
+            )
+            .map_err(|err| Error::from_description("copying output texture to canvas", err))?;
+
+        if let Some(debug_textures) = &mut self.debug_textures {
+            Renderer::render_debug_views(canvas, debug_textures, state)?;
+        }
+        Ok(())
+    }
+
+    fn render_debug_views(
+        canvas: &mut Canvas<Window>,
+        debug_textures: &mut DebugTextures<'_>,
+        state: &EmulatorState,
+    ) -> Result<()> {
+        let mut debug_data = graphics::DebugData::new(&state.nes);
+        if let Some(palette) = &state.palette {
+            debug_data.colors = palette.colors();
+        }
+
+        let colors_rect = Rect::new(512, 480 + 16 * 4, 32 * 16, 32 * 4);
+        graphics::update_colors_texture(&mut debug_textures.colors, &debug_data)?;
+        canvas
+            .copy(&debug_textures.colors, None, Some(colors_rect))
+            .map_err(|err| Error::from_description("copying colors texture to canvas", err))?;
+        canvas
+            .draw_rect(colors_rect)
+            .map_err(|err| Error::from_description("drawing colors border", err))?;
+
+        let palettes_rect = Rect::new(512, 480 + 32 * 4 + 16 * 4, 32 * 16, 32 * 2);
+        graphics::update_palettes_texture(&mut debug_textures.palettes, &debug_data)?;
+        canvas
+            .copy(&debug_textures.palettes, None, Some(palettes_rect))
+            .map_err(|err| Error::from_description("copying palettes texture to canvas", err))?;
+        canvas
+            .draw_rect(palettes_rect)
+            .map_err(|err| Error::from_description("drawing palettes border", err))?;
+
+        let oam_rect = Rect::new(512, 480, 16 * 32, 16 * 4);
+        graphics::update_oam_texture(&mut debug_textures.oam, &debug_data)?;
+        canvas
+            .copy(&debug_textures.oam, None, Some(oam_rect))
+            .map_err(|err| Error::from_description("copying oam texture to canvas", err))?;
+        canvas
+            .draw_rect(oam_rect)
+            .map_err(|err| Error::from_description("drawing palettes border", err))?;
+
+        for (bank_index, texture) in debug_textures.nametables.iter_mut().enumerate() {
+            graphics::update_nametable_texture(texture, &debug_data, bank_index)?;
+            canvas
+                .copy(
+                    texture,
+                    None,
+                    Some(Rect::new(
+                        512 + 256 * (bank_index as i32 % 2),
+                        240 * (bank_index as i32 / 2),
+                        256,
+                        240,
+                    )),
+                )
+                .map_err(|err| {
+                    Error::from_description("copying nametable texture to canvas", err)
+                })?;
+        }
+        canvas
+            .draw_rect(Rect::new(512, 0, 512, 480))
+            .map_err(|err| Error::from_description("drawing nametables border", err))?;
+
+        for (table_index, texture) in debug_textures.pattern_tables.iter_mut().enumerate() {
+            graphics::update_pattern_table_texture(texture, &debug_data, table_index)?;
+            canvas
+                .copy(
+                    texture,
+                    None,
+                    Some(Rect::new(table_index as i32 * 256, 480, 256, 256)),
+                )
+                .map_err(|err| {
+                    Error::from_description("copying pattern table texture to canvas", err)
+                })?;
+        }
+        Ok(())
+    }
+
+    // Logs the average time spent emulating and rendering a frame in debug mode.
+    pub fn record_frame_time(&mut self, frame_time: Duration) {
+        if self.debug_textures.is_none() {
+            return;
+        }
+
+        self.frame_time_total += frame_time;
+        self.frame_time_count += 1;
+        if self.frame_time_count == FRAME_TIME_SAMPLES {
+            let average_frame_time = self.frame_time_total / FRAME_TIME_SAMPLES;
+            info!(
+                "[GUI] Average frame time: {:.2} ms.",
+                average_frame_time.as_micros() as f64 / 1000.0
+            );
+            self.frame_time_total = Duration::default();
+            self.frame_time_count = 0;
+        }
+    }
+}
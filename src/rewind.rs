@@ -0,0 +1,86 @@
+use super::{Error, Result};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use neso::Nes;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+
+pub struct RewindBuffer {
+    snapshots: VecDeque<Vec<u8>>,
+    size: usize,
+    capacity: usize,
+    interval: u32,
+    frame_index: u32,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize, interval: u32) -> Self {
+        RewindBuffer {
+            snapshots: VecDeque::new(),
+            size: 0,
+            capacity,
+            interval: interval.max(1),
+            frame_index: 0,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn record_frame(&mut self, nes: &Nes) -> Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        self.frame_index = (self.frame_index + 1) % self.interval;
+        if self.frame_index != 0 {
+            return Ok(());
+        }
+
+        let data = nes
+            .save_state()
+            .map_err(|err| Error::new("getting rewind snapshot data", &err))?;
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        encoder
+            .write_all(&data)
+            .map_err(|err| Error::new("compressing rewind snapshot", &err))?;
+        let snapshot = encoder
+            .finish()
+            .map_err(|err| Error::new("compressing rewind snapshot", &err))?;
+
+        // Evict the oldest snapshots until the new snapshot fits in the buffer.
+        while self.size + snapshot.len() > self.capacity {
+            match self.snapshots.pop_front() {
+                Some(evicted_snapshot) => self.size -= evicted_snapshot.len(),
+                None => return Ok(()),
+            }
+        }
+        self.size += snapshot.len();
+        self.snapshots.push_back(snapshot);
+        Ok(())
+    }
+
+    // Returns `true` if a snapshot was loaded into `nes`.
+    pub fn rewind_frame(&mut self, nes: &mut Nes) -> Result<bool> {
+        self.frame_index = (self.frame_index + 1) % self.interval;
+        if self.frame_index != 0 {
+            return Ok(false);
+        }
+
+        let snapshot = match self.snapshots.pop_back() {
+            Some(snapshot) => snapshot,
+            None => return Ok(false),
+        };
+        self.size -= snapshot.len();
+
+        let mut data = Vec::new();
+        DeflateDecoder::new(&snapshot[..])
+            .read_to_end(&mut data)
+            .map_err(|err| Error::new("decompressing rewind snapshot", &err))?;
+        nes.load_state(&data)
+            .map_err(|err| Error::new("loading rewind snapshot", &err))?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,21 @@
+use super::{Error, Result};
+use log::info;
+use png::HasParameters;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+pub fn write_png<P>(path: P, width: u32, height: u32, data: &[u8]) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    info!("[GUI] Writing screenshot at {:?}.", path.as_ref());
+    let file = File::create(path).map_err(|err| Error::new("creating screenshot file", &err))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .map_err(|err| Error::new("writing screenshot header", &err))?
+        .write_image_data(data)
+        .map_err(|err| Error::new("writing screenshot data", &err))
+}